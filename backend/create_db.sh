@@ -20,11 +20,65 @@ CREATE TABLE users (
   name TEXT NOT NULL,                                -- optional
   password_hash TEXT,                                -- optional (for Google users)
   google_sub TEXT UNIQUE,                            -- optional, but must be unique if present
-  phone_num TEXT NOT NULL UNIQUE,                    -- optional, but must be unique if present
+  phone_num TEXT NOT NULL UNIQUE CHECK (phone_num ~ '^\+[1-9][0-9]{6,14}$'), -- E.164, normalized by the backend
   created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),     -- time of account creation
   last_seen_at TIMESTAMPTZ,                          -- optional last-seen timestamp
-  picture TEXT                                       -- optional avatar URL
+  picture TEXT,                                      -- optional avatar URL
+  roles TEXT[] NOT NULL DEFAULT '{}',                -- admin, support
+  deletion_scheduled_at TIMESTAMPTZ                  -- set when the user deletes the account, purged after this time
 );
 ALTER TABLE users OWNER TO $DB_USER;
 
+CREATE TABLE sessions (
+  id UUID PRIMARY KEY,                               -- same id as the refresh token family
+  user_uuid UUID NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+  method TEXT NOT NULL,                              -- password, google, sign_up...
+  user_agent TEXT,                                   -- optional, as sent by the client
+  ip TEXT,                                           -- optional, peer address at sign in
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),     -- time of sign in
+  last_used_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),   -- last token refresh
+  expires_at TIMESTAMPTZ NOT NULL,                   -- when the refresh token family expires
+  revoked_at TIMESTAMPTZ                             -- set on logout or remote sign out
+);
+CREATE INDEX sessions_user_uuid_idx ON sessions (user_uuid);
+ALTER TABLE sessions OWNER TO $DB_USER;
+
+CREATE TABLE role_audit_log (
+  id BIGSERIAL PRIMARY KEY,
+  actor_uuid UUID NOT NULL,                          -- admin who made the change, nil uuid once purged
+  target_uuid UUID NOT NULL,                         -- user whose roles changed
+  role TEXT NOT NULL,
+  action TEXT NOT NULL,                              -- grant or revoke
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+ALTER TABLE role_audit_log OWNER TO $DB_USER;
+
+CREATE TABLE totp_factors (
+  user_uuid UUID PRIMARY KEY REFERENCES users(uuid) ON DELETE CASCADE,
+  secret_encrypted TEXT NOT NULL,                    -- AES-256-GCM, nonce || ciphertext in base64
+  enabled_at TIMESTAMPTZ,                            -- null until confirmed with a first code
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+ALTER TABLE totp_factors OWNER TO $DB_USER;
+
+CREATE TABLE totp_recovery_codes (
+  id BIGSERIAL PRIMARY KEY,
+  user_uuid UUID NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+  code_hash TEXT NOT NULL,                           -- sha256 of the code
+  used_at TIMESTAMPTZ                                -- set once the code was used
+);
+CREATE INDEX totp_recovery_codes_user_uuid_idx ON totp_recovery_codes (user_uuid);
+ALTER TABLE totp_recovery_codes OWNER TO $DB_USER;
+
+CREATE TABLE webauthn_credentials (
+  credential_id TEXT PRIMARY KEY,                    -- base64url credential id from the authenticator
+  user_uuid UUID NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+  name TEXT NOT NULL,                                -- chosen by the user, e.g. "iPhone"
+  passkey TEXT NOT NULL,                             -- serialized passkey (public key, counter) as JSON
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_used_at TIMESTAMPTZ                           -- last successful sign in
+);
+CREATE INDEX webauthn_credentials_user_uuid_idx ON webauthn_credentials (user_uuid);
+ALTER TABLE webauthn_credentials OWNER TO $DB_USER;
+
 EOF
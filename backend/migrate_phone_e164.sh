@@ -0,0 +1,54 @@
+#!/bin/bash
+
+# Upgrades a database created before phone numbers were normalized to E.164 (create_db.sh now adds the
+# check to new databases). Safe to run more than once.
+# 1. Adds the check as NOT VALID, so new writes must be E.164 while old rows are left alone
+# 2. Rewrites old numbers to E.164. The old sign-up page only took Israeli mobiles, so local ones
+#    (05x...) get +972. A number that would collide with another account is left for a manual fix.
+# 3. Lists whatever still isn't E.164, and validates the check once nothing is left
+
+# Variables — customize as needed
+DB_NAME="test_db"
+
+sudo -u postgres psql -v ON_ERROR_STOP=1 -d "$DB_NAME" <<'EOF'
+DO $$
+BEGIN
+  IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'users_phone_num_check') THEN
+    ALTER TABLE users ADD CONSTRAINT users_phone_num_check CHECK (phone_num ~ '^\+[1-9][0-9]{6,14}$') NOT VALID;
+  END IF;
+END $$;
+
+WITH cleaned AS (
+  SELECT uuid, regexp_replace(phone_num, '[[:space:]().-]', '', 'g') AS phone
+  FROM users
+  WHERE phone_num !~ '^\+[1-9][0-9]{6,14}$'
+), normalized AS (
+  SELECT uuid, CASE
+    WHEN phone ~ '^\+' THEN phone
+    WHEN phone ~ '^00' THEN '+' || substr(phone, 3)
+    WHEN phone ~ '^972' THEN '+' || phone
+    WHEN phone ~ '^0' THEN '+972' || substr(phone, 2)
+    ELSE phone
+  END AS phone
+  FROM cleaned
+)
+UPDATE users SET phone_num = normalized.phone
+FROM normalized
+WHERE users.uuid = normalized.uuid
+  AND normalized.phone ~ '^\+[1-9][0-9]{6,14}$'
+  AND NOT EXISTS (SELECT 1 FROM users taken WHERE taken.phone_num = normalized.phone)
+  AND (SELECT count(*) FROM normalized same WHERE same.phone = normalized.phone) = 1;
+
+-- Left for a manual fix, the check stays NOT VALID until this is empty
+SELECT uuid, email, phone_num FROM users WHERE phone_num !~ '^\+[1-9][0-9]{6,14}$';
+
+DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM users WHERE phone_num !~ '^\+[1-9][0-9]{6,14}$') THEN
+    RAISE NOTICE 'Some phone numbers are not E.164 yet, fix the rows above and run this again';
+  ELSE
+    ALTER TABLE users VALIDATE CONSTRAINT users_phone_num_check;
+    RAISE NOTICE 'All phone numbers are E.164, users_phone_num_check is validated';
+  END IF;
+END $$;
+EOF
@@ -0,0 +1,129 @@
+use axum::{
+  extract::{Json, Path, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth::roles::{self as role_guard, Admin, RequireRole, Role, Support};
+use crate::auth::sessions;
+use crate::auth::db::roles;
+use crate::auth::db::user_data;
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct GrantRoleRequest {
+  role: Role,
+}
+
+#[derive(Serialize)]
+pub struct AdminRolesResponse {
+  error_code: Option<AdminRolesError>,
+  roles: Option<Vec<Role>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminRolesError {
+  UserNotFound,
+  RoleAlreadyGranted,
+  RoleNotGranted,
+  CannotRevokeOwnAdmin,
+  InternalError,
+}
+
+/*** Helpers ***/
+
+fn success_response(event: &str, actor_uuid: &Uuid, target_uuid: &Uuid, role: Role) -> (StatusCode, Json<AdminRolesResponse>) {
+  tracing::info!(
+    event = event,
+    actor_uuid = %actor_uuid,
+    target_uuid = %target_uuid,
+    role = role.as_str(),
+  );
+  (StatusCode::OK, Json(AdminRolesResponse {
+    error_code: None,
+    roles: None,
+  }))
+}
+
+fn warn_response(status_code: StatusCode, error_code: AdminRolesError) -> (StatusCode, Json<AdminRolesResponse>) {
+  tracing::warn!(
+    event = "admin_roles_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(AdminRolesResponse {
+    error_code: Some(error_code),
+    roles: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<AdminRolesResponse>) {
+  tracing::error!(
+    event = "admin_roles_failure",
+    error_code = ?(AdminRolesError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(AdminRolesResponse {
+    error_code: Some(AdminRolesError::InternalError),
+    roles: None,
+  }))
+}
+
+async fn user_exists(app_state: &AppState, uuid: &Uuid) -> Result<bool, sqlx::Error> {
+  Ok(user_data::get_user_by_uuid(&app_state.pool, uuid).await?.is_some())
+}
+
+/*** Handlers ***/
+
+// Read only, so support staff can check a user's roles too
+pub async fn handle_get_roles(State(app_state): State<AppState>, _support: RequireRole<Support>, Path(target_uuid): Path<Uuid>) -> (StatusCode, Json<AdminRolesResponse>) {
+  match user_data::get_user_by_uuid(&app_state.pool, &target_uuid).await {
+    Ok(Some(user)) => (StatusCode::OK, Json(AdminRolesResponse {
+      error_code: None,
+      roles: Some(role_guard::parse_roles(&user.roles)),
+    })),
+    Ok(None) => warn_response(StatusCode::NOT_FOUND, AdminRolesError::UserNotFound),
+    Err(_) => internal_error_response("db_error"),
+  }
+}
+
+pub async fn handle_grant_role(State(app_state): State<AppState>, admin: RequireRole<Admin>, Path(target_uuid): Path<Uuid>, Json(payload): Json<GrantRoleRequest>) -> (StatusCode, Json<AdminRolesResponse>) {
+  match user_exists(&app_state, &target_uuid).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::NOT_FOUND, AdminRolesError::UserNotFound),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  match roles::grant_role(&app_state.pool, &admin.auth.user_uuid, &target_uuid, payload.role.as_str()).await {
+    Ok(true) => success_response("role_grant_success", &admin.auth.user_uuid, &target_uuid, payload.role),
+    Ok(false) => warn_response(StatusCode::CONFLICT, AdminRolesError::RoleAlreadyGranted),
+    Err(_) => internal_error_response("db_error"),
+  }
+}
+
+pub async fn handle_revoke_role(State(app_state): State<AppState>, admin: RequireRole<Admin>, Path((target_uuid, role)): Path<(Uuid, Role)>) -> (StatusCode, Json<AdminRolesResponse>) {
+  // Keeps the last admin from locking everyone out by accident
+  if target_uuid == admin.auth.user_uuid && role == Role::Admin {
+    return warn_response(StatusCode::BAD_REQUEST, AdminRolesError::CannotRevokeOwnAdmin);
+  }
+  match user_exists(&app_state, &target_uuid).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::NOT_FOUND, AdminRolesError::UserNotFound),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  match roles::revoke_role(&app_state.pool, &admin.auth.user_uuid, &target_uuid, role.as_str()).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::NOT_FOUND, AdminRolesError::RoleNotGranted),
+    Err(_) => return internal_error_response("db_error"),
+  }
+  // Tokens already issued still carry the role, sign the user out so it's dropped right away
+  if sessions::revoke_all_sessions(&app_state, &target_uuid).await.is_err() {
+    return internal_error_response("session_revoke_failed");
+  }
+
+  success_response("role_revoke_success", &admin.auth.user_uuid, &target_uuid, role)
+}
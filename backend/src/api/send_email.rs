@@ -1,8 +1,14 @@
 use axum::http::StatusCode;
-use resend_rs::{Resend, Error, types::CreateEmailBaseOptions};
+use resend_rs::{Resend, Error, types::{CreateEmailBaseOptions, Tag}};
+
+use crate::request_id;
 
 pub async fn send_email(resend: &Resend, from: &str, to: Vec<&str>, subject: &str, html_body: &str) -> Result<(), Error> {
-  let email = CreateEmailBaseOptions::new(from, to, subject).with_html(html_body);
+  let mut email = CreateEmailBaseOptions::new(from, to, subject).with_html(html_body);
+  // Resend has no request header for this, a tag shows up next to the email in its dashboard
+  if let Some(id) = request_id::current() {
+    email = email.with_tag(Tag::new("request_id", &id));
+  }
   if let Err(error) = resend.emails.send(email).await {
     tracing::error!(
       event = "send_email_failure",
@@ -12,3 +18,12 @@ pub async fn send_email(resend: &Resend, from: &str, to: Vec<&str>, subject: &st
   }
   Ok(())
 }
+
+// User supplied values (names, emails) must go through this before being put in an html body
+pub fn escape_html(text: &str) -> String {
+  text.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
@@ -5,6 +5,9 @@ use rand::Rng;
 use deadpool_redis::Pool;
 
 use crate::auth::db::sms_code;
+use crate::telemetry;
+use crate::request_id;
+use crate::redact::{Code, Phone};
 
 #[derive(Serialize)]
 struct SmsAPIRequest {
@@ -13,8 +16,14 @@ struct SmsAPIRequest {
     to: String,
     from: String,
     text: String,
+    // Vonage drops request headers but echoes this back in delivery receipts
+    #[serde(rename = "client-ref", skip_serializing_if = "Option::is_none")]
+    client_ref: Option<String>,
 }
 
+// Longest client-ref Vonage accepts
+const MAX_CLIENT_REF_LEN: usize = 100;
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SmsCodeSendError {
@@ -57,14 +66,27 @@ pub async fn send_sms_code(pool: &Pool, uuid: &Uuid, expiration_time: u64, api_k
     );
     return Err(SmsCodeSendError::InternalError);
   }
+  // With log_reveal_pii on in dev the code can be read here instead of waiting for the SMS
+  tracing::debug!(
+    event = "sms_code_generated",
+    uuid = %uuid,
+    to = %Phone(to),
+    code = %Code(&code),
+  );
+
+  send_sms(uuid, api_key, api_secret, to, from, &(code + " " + text)).await
+}
 
-  let text: String = code + " " + text;
+// Sends a plain text message, `uuid` is only there to tie the logs to a user or flow
+pub async fn send_sms(uuid: &Uuid, api_key: &str, api_secret: &str, to: &str, from: &str, text: &str) -> Result<(), SmsCodeSendError> {
     let sms = SmsAPIRequest {
       api_key: api_key.to_string(),
       api_secret: api_secret.to_string(),
       to: to.to_string(),
       from: from.to_string(),
-      text: text,
+      text: text.to_string(),
+      // Request ids are ascii, cutting at a byte count is safe
+      client_ref: request_id::current().map(|id| id[..id.len().min(MAX_CLIENT_REF_LEN)].to_string()),
   };
 
   let client = Client::new();
@@ -73,7 +95,10 @@ pub async fn send_sms_code(pool: &Pool, uuid: &Uuid, expiration_time: u64, api_k
     .json(&sms)
     .send()
     .await
-    .map_err(|_| SmsCodeSendError::APIConnectionError)?;
+    .map_err(|_| {
+      telemetry::record_sms_send("connection_error");
+      SmsCodeSendError::APIConnectionError
+    })?;
 
   let status = res.status();
   if !status.is_success() {
@@ -82,12 +107,14 @@ pub async fn send_sms_code(pool: &Pool, uuid: &Uuid, expiration_time: u64, api_k
         http_status = status.as_u16(),
         uuid = %uuid,
       );
+      telemetry::record_sms_send(&format!("http_{}", status.as_u16()));
       return Err(SmsCodeSendError::APIConnectionError);
   }
 
   let res_body: SmsAPIResponse = res.json().await.map_err(|_| SmsCodeSendError::DeserializationError)?;
 
   for msg in res_body.messages {
+    telemetry::record_sms_send(&msg.status);
     if msg.status != "0" {
       let error = match msg.status.as_str() {
         "5" => SmsCodeSendError::APIInternalError,
@@ -102,7 +129,7 @@ pub async fn send_sms_code(pool: &Pool, uuid: &Uuid, expiration_time: u64, api_k
       tracing::warn!(
         event = "send_sms_code_failure",
         uuid = %uuid,
-        to = msg.to,
+        to = %Phone(&msg.to),
         status = msg.status,
         error_text = msg.error_text.as_deref().unwrap_or(""),
       );
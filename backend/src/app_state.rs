@@ -1,57 +1,192 @@
-use std::env::var;
+use std::path::Path;
+use std::sync::Arc;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use deadpool_redis::{Config, Pool, Runtime};
+use deadpool_redis::{Config as RedisConfig, Pool, Runtime};
 use resend_rs::Resend;
 
+use webauthn_rs::Webauthn;
+use metrics_exporter_prometheus::PrometheusHandle;
+
+use crate::config::Config;
+use crate::telemetry;
+use crate::auth::jwt_keys::{self, JwtKeys};
+use crate::auth::webauthn;
+use crate::auth::password_policy::PasswordPolicy;
+
 #[derive(Clone)]
 pub struct AppState {
   pub pool: PgPool,
   pub redis_pool: Pool, 
   pub resend: Resend,
-  pub jwt_secret: String,
+  pub jwt_keys: Arc<JwtKeys>,
+  pub webauthn: Arc<Webauthn>,
+  pub password_policy: Arc<PasswordPolicy>,
+  pub metrics: PrometheusHandle,
+  pub jwt_secret: String, // Only signs email link tokens, which never leave this service
+  pub totp_encryption_key: [u8; 32],
   pub google_console_client_id: String,
   pub captcha_secret_key: String,
   pub vonage_api_key: String,
   pub vonage_api_secret: String,
   pub company_phone: String,
-  pub jwt_expiration_days: i64,
+  pub email_from: String,
+  pub app_base_url: String,
+  pub access_token_expiration_sec: i64,
+  pub refresh_token_expiration_days: u64,
   pub max_sign_in_attempts: u32,
   pub sign_in_attempts_lock_sec: i64,
   pub sign_up_session_expiration_sec: u64,
   pub sms_code_expiration_sec: u64,
   pub sms_code_resend_sec: i64,
   pub sms_code_max_attemps: u32,
+  pub email_verification_expiration_sec: u64,
+  pub email_verification_resend_sec: u64,
+  pub password_reset_expiration_sec: u64,
+  pub password_reset_resend_sec: u64,
+  pub email_change_expiration_sec: u64,
+  pub email_change_resend_sec: u64,
+  pub magic_link_expiration_sec: u64,
+  pub magic_link_resend_sec: u64,
+  pub picture_allowed_hosts: Vec<String>,
+  pub phone_default_country: String,
+  pub phone_allowed_countries: Vec<String>,
+  pub reauth_max_age_sec: i64,
+  pub account_deletion_grace_days: i64,
+  pub sign_in_challenge_expiration_sec: u64,
+  pub sign_in_challenge_max_attempts: u32,
+  pub second_factor_max_failures: u32,
+  pub second_factor_lock_sec: i64,
+  pub webauthn_challenge_expiration_sec: u64,
+  pub health_check_timeout_ms: u64,
+}
+
+pub async fn create_app_state(config: &Config) -> AppState {
+  let breached_passwords_file = Some(config.breached_passwords_file.as_str()).filter(|path| !path.is_empty());
+  AppState {
+    pool: create_pg_pool(config).await,
+    redis_pool: create_redis_pool(config).await,
+    resend: Resend::new(config.resend_api_key.expose()),
+    jwt_keys: Arc::new(load_jwt_keys(config)),
+    webauthn: Arc::new(webauthn::build_webauthn(&config.app_base_url).expect("Unable to set up webauthn from app_base_url")),
+    password_policy: Arc::new(PasswordPolicy::new(config.password_min_length, config.password_min_strength, breached_passwords_file)
+      .expect("Unable to load breached_passwords_file")),
+    metrics: telemetry::install_recorder(),
+    jwt_secret: config.jwt_secret.expose().to_string(),
+    totp_encryption_key: load_totp_encryption_key(config),
+    google_console_client_id: config.google_console_client_id.clone(),
+    captcha_secret_key: config.captcha_secret_key.expose().to_string(),
+    vonage_api_key: config.vonage_api_key.expose().to_string(),
+    vonage_api_secret: config.vonage_api_secret.expose().to_string(),
+    company_phone: config.company_phone.clone(),
+    email_from: config.email_from.clone(),
+    app_base_url: config.app_base_url.clone(),
+    access_token_expiration_sec: config.access_token_expiration_sec,
+    refresh_token_expiration_days: config.refresh_token_expiration_days,
+    max_sign_in_attempts: config.max_sign_in_attempts,
+    sign_in_attempts_lock_sec: config.sign_in_attempts_lock_sec,
+    sign_up_session_expiration_sec: config.sign_up_session_expiration_sec,
+    sms_code_expiration_sec: config.sms_code_expiration_sec,
+    sms_code_resend_sec: config.sms_code_resend_sec,
+    sms_code_max_attemps: config.sms_code_max_attempts,
+    email_verification_expiration_sec: config.email_verification_expiration_sec,
+    email_verification_resend_sec: config.email_verification_resend_sec,
+    password_reset_expiration_sec: config.password_reset_expiration_sec,
+    password_reset_resend_sec: config.password_reset_resend_sec,
+    email_change_expiration_sec: config.email_change_expiration_sec,
+    email_change_resend_sec: config.email_change_resend_sec,
+    magic_link_expiration_sec: config.magic_link_expiration_sec,
+    magic_link_resend_sec: config.magic_link_resend_sec,
+    picture_allowed_hosts: config.picture_allowed_hosts.clone(),
+    phone_default_country: config.phone_default_country.clone(),
+    phone_allowed_countries: config.phone_allowed_countries.clone(),
+    reauth_max_age_sec: config.reauth_max_age_sec,
+    account_deletion_grace_days: config.account_deletion_grace_days,
+    sign_in_challenge_expiration_sec: config.sign_in_challenge_expiration_sec,
+    sign_in_challenge_max_attempts: config.sign_in_challenge_max_attempts,
+    second_factor_max_failures: config.second_factor_max_failures,
+    second_factor_lock_sec: config.second_factor_lock_sec,
+    webauthn_challenge_expiration_sec: config.webauthn_challenge_expiration_sec,
+    health_check_timeout_ms: config.health_check_timeout_ms,
+  }
+}
+
+async fn create_pg_pool(config: &Config) -> PgPool {
+  PgPoolOptions::new().max_connections(config.database_max_connections).connect(config.database_url.expose()).await.expect("Failed to connect to the database")
+}
+
+async fn create_redis_pool(config: &Config) -> Pool {
+  let redis_config = RedisConfig::from_url(config.redis_url.expose());
+  redis_config.create_pool(Some(Runtime::Tokio1)).expect("Unable to create redis connection pool")
 }
 
-pub async fn create_app_state() -> AppState {
+fn load_jwt_keys(config: &Config) -> JwtKeys {
+  jwt_keys::load_jwt_keys(Path::new(&config.jwt_keys_dir), &config.jwt_active_kid).expect("Unable to load JWT signing keys")
+}
+
+// Already checked to be 32 bytes of hex by the config validation
+fn load_totp_encryption_key(config: &Config) -> [u8; 32] {
+  hex::decode(config.totp_encryption_key.expose()).ok()
+    .and_then(|key| key.try_into().ok())
+    .expect("totp_encryption_key must be 32 bytes of hex")
+}
+
+// Handler tests get a state on the dev database (the sqlx macros need DATABASE_URL to build anyway).
+// Nothing connects until a handler runs a query, redis and Resend are never reached by the paths tested.
+#[cfg(test)]
+pub fn test_app_state() -> AppState {
+  use metrics_exporter_prometheus::PrometheusBuilder;
+
+  let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set to run the handler tests");
+  let keys_dir = std::env::temp_dir().join(format!("test_jwt_keys_{}", uuid::Uuid::new_v4()));
+  std::fs::create_dir_all(&keys_dir).unwrap();
+  jwt_keys::write_test_key(&keys_dir, "test", 7, true);
+  let jwt_keys = jwt_keys::load_jwt_keys(&keys_dir, "test").unwrap();
+  let _ = std::fs::remove_dir_all(&keys_dir);
+
   AppState {
-    pool: create_pg_pool().await,
-    redis_pool: create_redis_pool().await,
-    resend: Resend::new(&var("RESEND_API_KEY").expect("RESEND_API_KEY var must be set")),
-    jwt_secret: var("JWT_SECRET").expect("JWT_SECRET var must be set"),
-    google_console_client_id: var("GOOGLE_CONSOLE_CLIENT_ID").expect("GOOGLE_CONSOLE_CLIENT_ID var must be set"),
-    captcha_secret_key: var("CAPTCHA_SECRET_KEY").expect("CAPTCHA_SECRET_KEY var must be set"),  
-    vonage_api_key: var("VONAGE_API_KEY").expect("VONAGE_API_KEY var must be set"),  
-    vonage_api_secret: var("VONAGE_API_SECRET").expect("VONAGE_API_SECRET var must be set"),
+    pool: PgPoolOptions::new().max_connections(2).connect_lazy(&database_url).unwrap(),
+    redis_pool: RedisConfig::from_url("redis://127.0.0.1").create_pool(Some(Runtime::Tokio1)).unwrap(),
+    resend: Resend::new("re_test"),
+    jwt_keys: Arc::new(jwt_keys),
+    webauthn: Arc::new(webauthn::build_webauthn("https://getly.app").unwrap()),
+    password_policy: Arc::new(PasswordPolicy::new(8, 2, None).unwrap()),
+    metrics: PrometheusBuilder::new().build_recorder().handle(),
+    jwt_secret: "test-secret".to_string(),
+    totp_encryption_key: [1; 32],
+    google_console_client_id: "client-id".to_string(),
+    captcha_secret_key: "captcha".to_string(),
+    vonage_api_key: "vonage-key".to_string(),
+    vonage_api_secret: "vonage-secret".to_string(),
     company_phone: "972585339500".to_string(),
-    jwt_expiration_days: 30,
+    email_from: "no-reply@getly.app".to_string(),
+    app_base_url: "https://getly.app".to_string(),
+    access_token_expiration_sec: 900,
+    refresh_token_expiration_days: 30,
     max_sign_in_attempts: 10,
     sign_in_attempts_lock_sec: 300,
     sign_up_session_expiration_sec: 900,
     sms_code_expiration_sec: 300,
     sms_code_resend_sec: 180,
     sms_code_max_attemps: 5,
+    email_verification_expiration_sec: 86400,
+    email_verification_resend_sec: 60,
+    password_reset_expiration_sec: 1800,
+    password_reset_resend_sec: 60,
+    email_change_expiration_sec: 86400,
+    email_change_resend_sec: 60,
+    magic_link_expiration_sec: 900,
+    magic_link_resend_sec: 60,
+    picture_allowed_hosts: vec!["lh3.googleusercontent.com".to_string()],
+    phone_default_country: "IL".to_string(),
+    phone_allowed_countries: vec!["IL".to_string()],
+    reauth_max_age_sec: 300,
+    account_deletion_grace_days: 30,
+    sign_in_challenge_expiration_sec: 300,
+    sign_in_challenge_max_attempts: 5,
+    second_factor_max_failures: 10,
+    second_factor_lock_sec: 3600,
+    webauthn_challenge_expiration_sec: 300,
+    health_check_timeout_ms: 2000,
   }
 }
-
-async fn create_pg_pool() -> PgPool {
-  let url = var("DATABASE_URL").expect("DATABASE_URL var must be set");
-  PgPoolOptions::new().max_connections(5).connect(&url).await.expect("Failed to connect to the database")
-}
-
-async fn create_redis_pool() -> Pool {
-  let url = var("REDIS_URL").expect("REDIS_URL var must be set");
-  let config = Config::from_url(&url);
-  config.create_pool(Some(Runtime::Tokio1)).expect("Unable to create redis connection pool")
-}
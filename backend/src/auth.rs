@@ -7,3 +7,26 @@ pub mod sign_up_start;
 pub mod sign_up_sms;
 pub mod sign_up_complete;
 pub mod captcha;
+pub mod email_link;
+pub mod verify_email;
+pub mod password_reset;
+pub mod refresh;
+pub mod logout;
+pub mod client_info;
+pub mod sessions;
+pub mod jwt_keys;
+pub mod auth_user;
+pub mod roles;
+pub mod password_policy;
+pub mod security_notification;
+pub mod change_password;
+pub mod sign_in_methods;
+pub mod change_email;
+pub mod totp;
+pub mod two_factor;
+pub mod webauthn;
+pub mod passkeys;
+pub mod sms_sign_in;
+pub mod magic_link;
+pub mod phone_number;
+pub mod change_phone;
@@ -0,0 +1,119 @@
+use axum::{
+  extract::{FromRequestParts, Json},
+  http::{StatusCode, request::Parts},
+  response::{IntoResponse, Response},
+};
+use axum_extra::{
+    extract::TypedHeader,
+    headers::{Authorization, authorization::Bearer},
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::request_id;
+use crate::auth::jwt::{self, JWTClaims, JWTError};
+use crate::auth::db::user_data::UserData;
+
+/*** Json Structs **/
+
+#[derive(Serialize)]
+pub struct AuthErrorResponse {
+  error_code: AuthError,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthError {
+  MissingToken,
+  TokenExpired,
+  InvalidToken,
+  Forbidden,
+  InternalError,
+}
+
+pub struct AuthRejection(pub AuthError);
+
+impl IntoResponse for AuthRejection {
+  fn into_response(self) -> Response {
+    let status_code = match self.0 {
+      AuthError::InternalError => {
+        tracing::error!(
+          event = "auth_failure",
+          error_code = ?(self.0),
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+      },
+      AuthError::Forbidden => {
+        tracing::warn!(
+          event = "auth_failure",
+          error_code = ?(self.0),
+        );
+        StatusCode::FORBIDDEN
+      },
+      _ => {
+        tracing::warn!(
+          event = "auth_failure",
+          error_code = ?(self.0),
+        );
+        StatusCode::UNAUTHORIZED
+      },
+    };
+    (status_code, Json(AuthErrorResponse { error_code: self.0 })).into_response()
+  }
+}
+
+impl From<JWTError> for AuthRejection {
+  fn from(error: JWTError) -> Self {
+    match error {
+      JWTError::ExpiredToken => AuthRejection(AuthError::TokenExpired),
+      JWTError::InternalError => AuthRejection(AuthError::InternalError),
+      _ => AuthRejection(AuthError::InvalidToken),
+    }
+  }
+}
+
+/*** Helpers ***/
+
+async fn bearer_token(parts: &mut Parts, state: &AppState) -> Result<String, AuthRejection> {
+  let TypedHeader(Authorization(bearer)) = TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state).await
+    .map_err(|_| AuthRejection(AuthError::MissingToken))?;
+  Ok(bearer.token().to_string())
+}
+
+/*** Extractors ***/
+
+// Authenticated user for protected routes, loads the user row on every request
+pub struct AuthUser {
+  pub user: UserData,
+  pub claims: JWTClaims,
+}
+
+// Lightweight variant that only checks the token, for routes that don't need the user row
+pub struct AuthClaims {
+  pub user_uuid: Uuid,
+  pub claims: JWTClaims,
+}
+
+impl FromRequestParts<AppState> for AuthUser {
+  type Rejection = AuthRejection;
+
+  async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+    let token = bearer_token(parts, state).await?;
+    let (claims, user) = jwt::verify_jwt_token(&state.pool, &state.redis_pool, &token, &state.jwt_keys).await?;
+    request_id::record_user(&user.uuid);
+    Ok(AuthUser { user, claims })
+  }
+}
+
+impl FromRequestParts<AppState> for AuthClaims {
+  type Rejection = AuthRejection;
+
+  async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+    let token = bearer_token(parts, state).await?;
+    let claims = jwt::verify_jwt_claims(&state.redis_pool, &token, &state.jwt_keys).await?;
+    let user_uuid = Uuid::parse_str(&claims.sub).map_err(|_| AuthRejection(AuthError::InvalidToken))?;
+    request_id::record_user(&user_uuid);
+    Ok(AuthClaims { user_uuid, claims })
+  }
+}
@@ -1,6 +1,8 @@
 use reqwest::{Client, Error};
 use serde::Deserialize;
 
+use crate::request_id;
+
 #[derive(Deserialize)]
 struct RecaptchaResponse {
     success: bool,
@@ -13,8 +15,11 @@ pub async fn verify_recaptcha(token: &str, secret_key: &str) -> Result<bool, Err
     ("response", token),
   ];
 
-  let response = client.post("https://www.google.com/recaptcha/api/siteverify")
-    .form(&params).send().await?.json::<RecaptchaResponse>().await?;
+  let mut request = client.post("https://www.google.com/recaptcha/api/siteverify").form(&params);
+  if let Some(id) = request_id::current() {
+    request = request.header(request_id::REQUEST_ID_HEADER, id);
+  }
+  let response = request.send().await?.json::<RecaptchaResponse>().await?;
 
   Ok(response.success)
 }
@@ -0,0 +1,278 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::app_state::AppState;
+use crate::api::send_email::{send_email, escape_html};
+use crate::auth::auth_user::AuthUser;
+use crate::auth::email_link::{self, EmailLinkError};
+use crate::auth::hashing::verify_password;
+use crate::auth::sessions;
+use crate::auth::db::email_link as email_link_db;
+use crate::auth::db::email_change::{self, PreviousEmail};
+use crate::auth::db::user_data::{self, UserData};
+use crate::auth::db::sign_in_attempts;
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+  new_email: String,
+  password: Option<String>, // Required when the account has a password, otherwise a recent sign in is
+}
+
+#[derive(Deserialize)]
+pub struct EmailChangeLinkRequest {
+  token: String,
+}
+
+#[derive(Serialize)]
+pub struct ChangeEmailResponse {
+  error_code: Option<ChangeEmailError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeEmailError {
+  InvalidEmail,
+  SameEmail,
+  EmailTaken,
+  InvalidCredentials,
+  NeedToReauthenticate,
+  NeedToWaitBeforeResend,
+  InvalidToken,
+  LinkAlreadyUsed,
+  NoPendingChange,
+  InternalError,
+}
+
+const CONFIRM_EMAIL_CHANGE_PAGE: &str = "confirm-email-change.html";
+const CANCEL_EMAIL_CHANGE_PAGE: &str = "cancel-email-change.html";
+
+/*** Helpers ***/
+
+async fn send_change_emails(app_state: &AppState, user: &UserData, new_email: &str) -> Result<(), anyhow::Error> {
+  let expiration_sec = app_state.email_change_expiration_sec;
+  let confirm_token = email_link::create_link_token(&app_state.redis_pool, &app_state.jwt_secret, email_link::CHANGE_EMAIL, &user.uuid, new_email, expiration_sec)
+    .await.map_err(|error| anyhow::anyhow!("{:?}", error))?;
+  // Sent to the old address but names the new one, so it can only cancel this change and not a later one
+  let cancel_token = email_link::create_link_token(&app_state.redis_pool, &app_state.jwt_secret, email_link::CANCEL_EMAIL_CHANGE, &user.uuid, new_email, expiration_sec)
+    .await.map_err(|error| anyhow::anyhow!("{:?}", error))?;
+
+  let confirm_url = email_link::link_url(&app_state.app_base_url, CONFIRM_EMAIL_CHANGE_PAGE, &confirm_token);
+  let confirm_body = format!(
+    "<p>Hi {},</p>\
+     <p>Please confirm this is the new email address for your Getly account by clicking the link below:</p>\
+     <p><a href=\"{confirm_url}\">Confirm my new email</a></p>\
+     <p>The link will last for {} hours. If you didn't ask for this, you can ignore this email.</p>",
+    escape_html(&user.name),
+    expiration_sec / 3600,
+  );
+  send_email(&app_state.resend, &app_state.email_from, vec![new_email], "Confirm your new Getly email", &confirm_body).await?;
+
+  let cancel_url = email_link::link_url(&app_state.app_base_url, CANCEL_EMAIL_CHANGE_PAGE, &cancel_token);
+  let cancel_body = format!(
+    "<p>Hi {},</p>\
+     <p>We received a request to change the email address of your Getly account to {}. \
+     It will only change once the new address is confirmed.</p>\
+     <p>If this wasn't you, <a href=\"{cancel_url}\">cancel the change</a> and reset your password. \
+     The link keeps working for {} hours, even if the change was already confirmed.</p>",
+    escape_html(&user.name),
+    escape_html(new_email),
+    expiration_sec / 3600,
+  );
+  send_email(&app_state.resend, &app_state.email_from, vec![&user.email], "Your Getly email is about to change", &cancel_body).await?;
+  Ok(())
+}
+
+fn success_response(event: &str) -> (StatusCode, Json<ChangeEmailResponse>) {
+  tracing::info!(event = event);
+  (StatusCode::OK, Json(ChangeEmailResponse {
+    error_code: None,
+  }))
+}
+
+fn warn_response(status_code: StatusCode, error_code: ChangeEmailError) -> (StatusCode, Json<ChangeEmailResponse>) {
+  tracing::warn!(
+    event = "change_email_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(ChangeEmailResponse {
+    error_code: Some(error_code),
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<ChangeEmailResponse>) {
+  tracing::error!(
+    event = "change_email_failure",
+    error_code = ?(ChangeEmailError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(ChangeEmailResponse {
+    error_code: Some(ChangeEmailError::InternalError),
+  }))
+}
+
+fn link_error_response(error: EmailLinkError) -> (StatusCode, Json<ChangeEmailResponse>) {
+  match error {
+    EmailLinkError::InvalidToken => warn_response(StatusCode::UNAUTHORIZED, ChangeEmailError::InvalidToken),
+    EmailLinkError::AlreadyUsed => warn_response(StatusCode::GONE, ChangeEmailError::LinkAlreadyUsed),
+    EmailLinkError::InternalError => internal_error_response("cannot_connect_to_redis"),
+  }
+}
+
+/*** Handlers ***/
+
+// Only sends the links, users.email is swapped once the new address is confirmed
+pub async fn handle_request_change(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser, Json(payload): Json<ChangeEmailRequest>) -> (StatusCode, Json<ChangeEmailResponse>) {
+  let new_email = payload.new_email.trim();
+  if !new_email.contains('@') {
+    return warn_response(StatusCode::BAD_REQUEST, ChangeEmailError::InvalidEmail);
+  }
+  if new_email.eq_ignore_ascii_case(&user.email) {
+    return warn_response(StatusCode::BAD_REQUEST, ChangeEmailError::SameEmail);
+  }
+
+  // A stolen access token alone shouldn't be enough to take over the account
+  if let Some(password_hash) = &user.password_hash {
+    if let Ok(true) = sign_in_attempts::is_locked(&app_state.redis_pool, &user.email, app_state.max_sign_in_attempts).await {
+      return warn_response(StatusCode::UNAUTHORIZED, ChangeEmailError::InvalidCredentials);
+    }
+    if !payload.password.as_deref().is_some_and(|password| verify_password(password, password_hash)) {
+      let _ = sign_in_attempts::increament_sign_in_attempts(&app_state.redis_pool, &user.email, app_state.sign_in_attempts_lock_sec).await;
+      return warn_response(StatusCode::UNAUTHORIZED, ChangeEmailError::InvalidCredentials);
+    }
+    let _ = sign_in_attempts::clear_sign_in_attempts(&app_state.redis_pool, &user.email).await;
+  } else {
+    // Without a password to ask for, the user must have signed in just now (e.g. with Google)
+    match sessions::is_recently_authenticated(&app_state, &claims).await {
+      Ok(true) => {},
+      Ok(false) => return warn_response(StatusCode::FORBIDDEN, ChangeEmailError::NeedToReauthenticate),
+      Err(_) => return internal_error_response("db_error"),
+    }
+  }
+
+  match user_data::get_user_by_email(&app_state.pool, new_email).await {
+    Ok(None) => {},
+    Ok(Some(_)) => return warn_response(StatusCode::CONFLICT, ChangeEmailError::EmailTaken),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  match email_link_db::try_start_resend_cooldown(&app_state.redis_pool, email_link::CHANGE_EMAIL, &user.uuid.to_string(), app_state.email_change_resend_sec).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::TOO_MANY_REQUESTS, ChangeEmailError::NeedToWaitBeforeResend),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  if email_change::store_pending_change(&app_state.redis_pool, &user.uuid, new_email, app_state.email_change_expiration_sec).await.is_err() {
+    return internal_error_response("cannot_connect_to_redis");
+  }
+  if send_change_emails(&app_state, &user, new_email).await.is_err() {
+    return internal_error_response("send_email_failed");
+  }
+
+  tracing::info!(
+    event = "change_email_request_success",
+    user_uuid = %user.uuid,
+  );
+  success_response("change_email_request")
+}
+
+pub async fn handle_confirm_change(State(app_state): State<AppState>, Json(payload): Json<EmailChangeLinkRequest>) -> (StatusCode, Json<ChangeEmailResponse>) {
+  let claims = match email_link::consume_link_token(&app_state.redis_pool, &app_state.jwt_secret, email_link::CHANGE_EMAIL, &payload.token).await {
+    Ok(claims) => claims,
+    Err(error) => return link_error_response(error),
+  };
+
+  // Refuses links from cancelled or replaced requests
+  match email_change::take_pending_change(&app_state.redis_pool, &claims.sub, &claims.email).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::CONFLICT, ChangeEmailError::NoPendingChange),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  let previous = match user_data::get_user_by_uuid(&app_state.pool, &claims.sub).await {
+    Ok(Some(user)) => PreviousEmail {
+      email: user.email,
+      email_verified: user.email_verified,
+    },
+    Ok(None) => return warn_response(StatusCode::UNAUTHORIZED, ChangeEmailError::InvalidToken),
+    Err(_) => return internal_error_response("db_error"),
+  };
+  match user_data::change_email(&app_state.pool, &claims.sub, &claims.email).await {
+    Ok(()) => {},
+    // Someone signed up with the address after the change was requested
+    Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+      return warn_response(StatusCode::CONFLICT, ChangeEmailError::EmailTaken);
+    },
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  // The cancel link was made together with this one, so it expires at the same time
+  let cancel_window_sec = (claims.exp - OffsetDateTime::now_utc().unix_timestamp()).max(1) as u64;
+  if email_change::store_previous_email(&app_state.redis_pool, &claims.sub, &claims.email, &previous, cancel_window_sec).await.is_err() {
+    tracing::error!(
+      event = "change_email_confirm_failure",
+      user_uuid = %claims.sub,
+      reason = "cannot_store_previous_email",
+    );
+  }
+
+  tracing::info!(
+    event = "change_email_success",
+    user_uuid = %claims.sub,
+  );
+  success_response("change_email_confirm")
+}
+
+// Sent to the old address. Drops the change while it's pending, and once confirmed puts the old
+// address back and signs everyone out, since whoever confirmed might not be the owner
+pub async fn handle_cancel_change(State(app_state): State<AppState>, Json(payload): Json<EmailChangeLinkRequest>) -> (StatusCode, Json<ChangeEmailResponse>) {
+  let claims = match email_link::consume_link_token(&app_state.redis_pool, &app_state.jwt_secret, email_link::CANCEL_EMAIL_CHANGE, &payload.token).await {
+    Ok(claims) => claims,
+    Err(error) => return link_error_response(error),
+  };
+
+  match email_change::take_pending_change(&app_state.redis_pool, &claims.sub, &claims.email).await {
+    Ok(true) => {
+      tracing::info!(
+        event = "change_email_cancel_success",
+        user_uuid = %claims.sub,
+      );
+      return success_response("change_email_cancel");
+    },
+    Ok(false) => {},
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  let previous = match email_change::take_previous_email(&app_state.redis_pool, &claims.sub, &claims.email).await {
+    Ok(Some(previous)) => previous,
+    Ok(None) => return warn_response(StatusCode::CONFLICT, ChangeEmailError::NoPendingChange),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  };
+  match user_data::restore_email(&app_state.pool, &claims.sub, &claims.email, &previous.email, previous.email_verified).await {
+    Ok(true) => {},
+    // The email was changed again since, this link is about an older change
+    Ok(false) => return warn_response(StatusCode::CONFLICT, ChangeEmailError::NoPendingChange),
+    Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+      return warn_response(StatusCode::CONFLICT, ChangeEmailError::EmailTaken);
+    },
+    Err(_) => return internal_error_response("db_error"),
+  }
+  // A change requested since then came from the same hands, its confirm link mustn't work either
+  if email_change::clear_pending_change(&app_state.redis_pool, &claims.sub).await.is_err() {
+    return internal_error_response("cannot_connect_to_redis");
+  }
+  if sessions::revoke_all_sessions(&app_state, &claims.sub).await.is_err() {
+    return internal_error_response("session_revoke_failed");
+  }
+
+  tracing::info!(
+    event = "change_email_revert_success",
+    user_uuid = %claims.sub,
+  );
+  success_response("change_email_revert")
+}
@@ -0,0 +1,123 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthUser;
+use crate::auth::hashing::{hash_password, verify_password};
+use crate::auth::password_policy::PasswordPolicyError;
+use crate::auth::security_notification::send_security_notification;
+use crate::auth::sessions;
+use crate::auth::db::user_data;
+use crate::auth::db::sign_in_attempts;
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+  current_password: String,
+  new_password: String,
+}
+
+#[derive(Serialize)]
+pub struct ChangePasswordResponse {
+  error_code: Option<ChangePasswordError>,
+  policy_error: Option<PasswordPolicyError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangePasswordError {
+  InvalidCredentials,
+  NoPasswordSet,
+  WeakPassword,
+  InternalError,
+}
+
+/*** Helpers ***/
+
+fn warn_response(status_code: StatusCode, error_code: ChangePasswordError) -> (StatusCode, Json<ChangePasswordResponse>) {
+  tracing::warn!(
+    event = "change_password_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(ChangePasswordResponse {
+    error_code: Some(error_code),
+    policy_error: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<ChangePasswordResponse>) {
+  tracing::error!(
+    event = "change_password_failure",
+    error_code = ?(ChangePasswordError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(ChangePasswordResponse {
+    error_code: Some(ChangePasswordError::InternalError),
+    policy_error: None,
+  }))
+}
+
+/*** Handlers ***/
+
+pub async fn handle_change_password(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser, Json(payload): Json<ChangePasswordRequest>) -> (StatusCode, Json<ChangePasswordResponse>) {
+  // Google only accounts have nothing to change, they need to link a password first
+  let Some(password_hash) = &user.password_hash else {
+    return warn_response(StatusCode::BAD_REQUEST, ChangePasswordError::NoPasswordSet);
+  };
+
+  // Shares the sign in lock, so a stolen access token can't be used to brute force the password
+  if let Ok(true) = sign_in_attempts::is_locked(&app_state.redis_pool, &user.email, app_state.max_sign_in_attempts).await {
+    return warn_response(StatusCode::UNAUTHORIZED, ChangePasswordError::InvalidCredentials);
+  }
+  if !verify_password(&payload.current_password, password_hash) {
+    let _ = sign_in_attempts::increament_sign_in_attempts(&app_state.redis_pool, &user.email, app_state.sign_in_attempts_lock_sec).await;
+    return warn_response(StatusCode::UNAUTHORIZED, ChangePasswordError::InvalidCredentials);
+  }
+  let _ = sign_in_attempts::clear_sign_in_attempts(&app_state.redis_pool, &user.email).await;
+
+  if let Err(policy_error) = app_state.password_policy.check(&payload.new_password, &user.email, &[&user.name]) {
+    tracing::warn!(
+      event = "change_password_failure",
+      error_code = ?(ChangePasswordError::WeakPassword),
+      policy_error = ?(policy_error),
+    );
+    return (StatusCode::BAD_REQUEST, Json(ChangePasswordResponse {
+      error_code: Some(ChangePasswordError::WeakPassword),
+      policy_error: Some(policy_error),
+    }));
+  }
+
+  let Ok(new_password_hash) = hash_password(&payload.new_password) else {
+    return internal_error_response("argon2_password_hashing_failed");
+  };
+  if user_data::change_password(&app_state.pool, &user.uuid, &new_password_hash).await.is_err() {
+    return internal_error_response("db_error");
+  }
+
+  // The device that made the change stays signed in
+  if sessions::revoke_other_sessions(&app_state, &user.uuid, &claims.sid).await.is_err() {
+    return internal_error_response("session_revoke_failed");
+  }
+
+  if send_security_notification(&app_state, &user, "Your Getly password was changed", "The password for your Getly account was just changed.").await.is_err() {
+    tracing::error!(
+      event = "change_password_notification_failure",
+      user_uuid = %user.uuid,
+      reason = "send_email_failed",
+    );
+  }
+
+  tracing::info!(
+    event = "change_password_success",
+    user_uuid = %user.uuid,
+    session_id = %claims.sid,
+  );
+  (StatusCode::OK, Json(ChangePasswordResponse {
+    error_code: None,
+    policy_error: None,
+  }))
+}
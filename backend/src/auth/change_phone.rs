@@ -0,0 +1,230 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::api::send_sms::{send_sms, send_sms_code, SmsCodeSendError};
+use crate::auth::auth_user::AuthUser;
+use crate::auth::hashing::verify_password;
+use crate::auth::sessions;
+use crate::auth::phone_number::{parse_sms_number, PhoneNumberError};
+use crate::auth::security_notification::send_security_notification;
+use crate::auth::db::phone_change::{self, PendingPhoneChange};
+use crate::auth::db::sms_code;
+use crate::auth::db::user_data::{self, UserData};
+use crate::auth::db::sign_in_attempts;
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct ChangePhoneRequest {
+  new_phone_num: String,
+  password: Option<String>, // Required when the account has a password, otherwise a recent sign in is
+}
+
+#[derive(Deserialize)]
+pub struct ChangePhoneVerifyRequest {
+  flow_id: Uuid,
+  code: String,
+}
+
+#[derive(Serialize)]
+pub struct ChangePhoneResponse {
+  error_code: Option<ChangePhoneError>,
+  flow_id: Option<Uuid>, // Sent back with the code to /auth/phone/change/verify
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangePhoneError {
+  InvalidNumber,
+  CountryNotAllowed,
+  NotMobileNumber,
+  SamePhoneNum,
+  PhoneNumTaken,
+  InvalidCredentials,
+  NeedToReauthenticate,
+  NeedToWaitBeforeResend,
+  FlowNotFound,
+  NeedToResendCode,
+  WrongCode,
+  TooManyAttempts,
+  APIError,
+  InternalError,
+}
+
+const SMS_MESSAGE: &str = "is your Getly code to confirm your new phone number. It will last for 5 minutes";
+const OLD_NUMBER_MESSAGE: &str = "The phone number on your Getly account was changed. If this wasn't you, reset your password right away.";
+
+/*** Helpers ***/
+
+fn response(status_code: StatusCode, error_code: Option<ChangePhoneError>, flow_id: Option<Uuid>) -> (StatusCode, Json<ChangePhoneResponse>) {
+  (status_code, Json(ChangePhoneResponse {
+    error_code,
+    flow_id,
+  }))
+}
+
+fn warn_response(status_code: StatusCode, error_code: ChangePhoneError) -> (StatusCode, Json<ChangePhoneResponse>) {
+  tracing::warn!(
+    event = "change_phone_failure",
+    error_code = ?(error_code),
+  );
+  response(status_code, Some(error_code), None)
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<ChangePhoneResponse>) {
+  tracing::error!(
+    event = "change_phone_failure",
+    error_code = ?(ChangePhoneError::InternalError),
+    reason = reason,
+  );
+  response(StatusCode::INTERNAL_SERVER_ERROR, Some(ChangePhoneError::InternalError), None)
+}
+
+fn send_error_response(error: SmsCodeSendError) -> (StatusCode, Json<ChangePhoneResponse>) {
+  match error {
+    SmsCodeSendError::APIConnectionError | SmsCodeSendError::APIInternalError | SmsCodeSendError::APIAccountError | SmsCodeSendError::TooManyRequests => {
+      tracing::error!(
+        event = "change_phone_failure",
+        error_code = ?(ChangePhoneError::APIError),
+      );
+      response(StatusCode::BAD_GATEWAY, Some(ChangePhoneError::APIError), None)
+    },
+    SmsCodeSendError::InvalidNumber => warn_response(StatusCode::UNPROCESSABLE_ENTITY, ChangePhoneError::InvalidNumber),
+    _ => internal_error_response("send_sms_failed"),
+  }
+}
+
+// Best effort, the number has already changed by now
+async fn notify_change(app_state: &AppState, user: &UserData) {
+  if send_sms(&user.uuid, &app_state.vonage_api_key, &app_state.vonage_api_secret, &user.phone_num, &app_state.company_phone, OLD_NUMBER_MESSAGE).await.is_err() {
+    tracing::error!(
+      event = "change_phone_notification_failure",
+      user_uuid = %user.uuid,
+      reason = "send_sms_failed",
+    );
+  }
+  if send_security_notification(app_state, user, "Your Getly phone number was changed", "The phone number on your Getly account was just changed.").await.is_err() {
+    tracing::error!(
+      event = "change_phone_notification_failure",
+      user_uuid = %user.uuid,
+      reason = "send_email_failed",
+    );
+  }
+}
+
+/*** Handlers ***/
+
+// Texts a code to the new number, users.phone_num is swapped once it's verified
+pub async fn handle_request_change(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser, Json(payload): Json<ChangePhoneRequest>) -> (StatusCode, Json<ChangePhoneResponse>) {
+  let new_phone_num = match parse_sms_number(&payload.new_phone_num, &app_state.phone_default_country, &app_state.phone_allowed_countries) {
+    Ok(number) => number.e164,
+    Err(PhoneNumberError::InvalidNumber) => return warn_response(StatusCode::UNPROCESSABLE_ENTITY, ChangePhoneError::InvalidNumber),
+    Err(PhoneNumberError::CountryNotAllowed) => return warn_response(StatusCode::UNPROCESSABLE_ENTITY, ChangePhoneError::CountryNotAllowed),
+    Err(PhoneNumberError::NotMobileNumber) => return warn_response(StatusCode::UNPROCESSABLE_ENTITY, ChangePhoneError::NotMobileNumber),
+  };
+  if new_phone_num == user.phone_num {
+    return warn_response(StatusCode::BAD_REQUEST, ChangePhoneError::SamePhoneNum);
+  }
+
+  // The phone can be used to sign in, so a stolen access token alone shouldn't be enough to swap it
+  if let Some(password_hash) = &user.password_hash {
+    if let Ok(true) = sign_in_attempts::is_locked(&app_state.redis_pool, &user.email, app_state.max_sign_in_attempts).await {
+      return warn_response(StatusCode::UNAUTHORIZED, ChangePhoneError::InvalidCredentials);
+    }
+    if !payload.password.as_deref().is_some_and(|password| verify_password(password, password_hash)) {
+      let _ = sign_in_attempts::increament_sign_in_attempts(&app_state.redis_pool, &user.email, app_state.sign_in_attempts_lock_sec).await;
+      return warn_response(StatusCode::UNAUTHORIZED, ChangePhoneError::InvalidCredentials);
+    }
+    let _ = sign_in_attempts::clear_sign_in_attempts(&app_state.redis_pool, &user.email).await;
+  } else {
+    match sessions::is_recently_authenticated(&app_state, &claims).await {
+      Ok(true) => {},
+      Ok(false) => return warn_response(StatusCode::FORBIDDEN, ChangePhoneError::NeedToReauthenticate),
+      Err(_) => return internal_error_response("db_error"),
+    }
+  }
+
+  match user_data::get_user_by_phone(&app_state.pool, &new_phone_num).await {
+    Ok(None) => {},
+    Ok(Some(_)) => return warn_response(StatusCode::CONFLICT, ChangePhoneError::PhoneNumTaken),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  match sms_code::try_start_resend_cooldown(&app_state.redis_pool, &new_phone_num, app_state.sms_code_resend_sec as u64).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::TOO_MANY_REQUESTS, ChangePhoneError::NeedToWaitBeforeResend),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  let flow_id = Uuid::new_v4();
+  let change = PendingPhoneChange {
+    user_uuid: user.uuid,
+    new_phone_num,
+  };
+  if phone_change::store_pending_change(&app_state.redis_pool, &flow_id, &change, app_state.sms_code_expiration_sec).await.is_err() {
+    return internal_error_response("cannot_connect_to_redis");
+  }
+  if let Err(error) = send_sms_code(&app_state.redis_pool, &flow_id, app_state.sms_code_expiration_sec, &app_state.vonage_api_key, &app_state.vonage_api_secret,
+      &change.new_phone_num, &app_state.company_phone, SMS_MESSAGE).await {
+    return send_error_response(error);
+  }
+
+  tracing::info!(
+    event = "change_phone_request_success",
+    user_uuid = %user.uuid,
+  );
+  response(StatusCode::OK, None, Some(flow_id))
+}
+
+pub async fn handle_verify_change(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser, Json(payload): Json<ChangePhoneVerifyRequest>) -> (StatusCode, Json<ChangePhoneResponse>) {
+  // Flows started by another account look the same as expired ones
+  let change = match phone_change::get_pending_change(&app_state.redis_pool, &payload.flow_id).await {
+    Ok(Some(change)) if change.user_uuid == user.uuid => change,
+    Ok(_) => return warn_response(StatusCode::NOT_FOUND, ChangePhoneError::FlowNotFound),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  };
+
+  if sms_code::get_code_exist(&app_state.redis_pool, &payload.flow_id).await.is_err() {
+    return warn_response(StatusCode::GONE, ChangePhoneError::NeedToResendCode);
+  }
+  match sms_code::verify_code(&app_state.redis_pool, &payload.flow_id, &payload.code, app_state.sms_code_max_attemps).await {
+    Ok(true) => {},
+    Ok(false) => {
+      return match sms_code::get_code_attempts_count(&app_state.redis_pool, &payload.flow_id).await {
+        Ok(attempts) if attempts > app_state.sms_code_max_attemps => warn_response(StatusCode::UNAUTHORIZED, ChangePhoneError::TooManyAttempts),
+        Ok(_) => warn_response(StatusCode::UNAUTHORIZED, ChangePhoneError::WrongCode),
+        Err(_) => internal_error_response("cannot_connect_to_redis"),
+      };
+    },
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  match phone_change::take_pending_change(&app_state.redis_pool, &payload.flow_id).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::NOT_FOUND, ChangePhoneError::FlowNotFound),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  match user_data::change_phone_num(&app_state.pool, &user.uuid, &change.new_phone_num).await {
+    Ok(()) => {},
+    // Someone else took the number after the code was sent
+    Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+      return warn_response(StatusCode::CONFLICT, ChangePhoneError::PhoneNumTaken);
+    },
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  // `user` still holds the old number, which is where the notice should go
+  notify_change(&app_state, &user).await;
+
+  tracing::info!(
+    event = "change_phone_success",
+    user_uuid = %user.uuid,
+  );
+  response(StatusCode::OK, None, None)
+}
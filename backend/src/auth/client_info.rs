@@ -0,0 +1,25 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use axum::{
+  extract::{ConnectInfo, FromRequestParts},
+  http::{header::USER_AGENT, request::Parts},
+};
+
+// What we know about the device making the request, recorded on every new session
+pub struct ClientInfo {
+  pub user_agent: Option<String>,
+  pub ip: Option<String>,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+  type Rejection = Infallible;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    let user_agent = parts.headers.get(USER_AGENT)
+      .and_then(|value| value.to_str().ok())
+      .map(str::to_string);
+    let ip = parts.extensions.get::<ConnectInfo<SocketAddr>>()
+      .map(|ConnectInfo(addr)| addr.ip().to_string());
+    Ok(ClientInfo { user_agent, ip })
+  }
+}
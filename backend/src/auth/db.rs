@@ -2,3 +2,18 @@ pub mod user_data;
 pub mod sign_in_attempts;
 pub mod sign_up_session;
 pub mod sms_code;
+pub mod email_link;
+pub mod token_version;
+pub mod password_reset;
+pub mod refresh_token;
+pub mod jwt_denylist;
+pub mod session;
+pub mod roles;
+pub mod email_change;
+pub mod totp;
+pub mod sign_in_challenge;
+pub mod webauthn_credential;
+pub mod webauthn_challenge;
+pub mod sms_sign_in;
+pub mod magic_link;
+pub mod phone_change;
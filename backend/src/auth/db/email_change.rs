@@ -0,0 +1,60 @@
+use serde::{Serialize, Deserialize};
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+// What a confirmed change replaced, put back if the old address cancels it
+#[derive(Serialize, Deserialize)]
+pub struct PreviousEmail {
+  pub email: String,
+  pub email_verified: bool,
+}
+
+// One pending change per user, a new request replaces the previous one
+pub async fn store_pending_change(pool: &Pool, user_uuid: &Uuid, new_email: &str, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("email_change:{}", user_uuid);
+  let _: () = conn.set_ex(&key, new_email, expiration_time).await?;
+  Ok(())
+}
+
+// Returns true only if the pending change is still for this address, so older links and cancelled changes are refused
+pub async fn take_pending_change(pool: &Pool, user_uuid: &Uuid, new_email: &str) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("email_change:{}", user_uuid);
+  // Compare and delete in one step, so a newer request can't be removed by an older link
+  let deleted: u32 = deadpool_redis::redis::cmd("EVAL")
+    .arg(r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("DEL", KEYS[1]) end return 0"#)
+    .arg(1)
+    .arg(&key)
+    .arg(new_email)
+    .query_async(&mut conn)
+    .await?;
+  Ok(deleted == 1)
+}
+
+// Drops whatever change is pending, for when the account is taken back from whoever changed the email
+pub async fn clear_pending_change(pool: &Pool, user_uuid: &Uuid) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("email_change:{}", user_uuid);
+  let _: () = conn.del(&key).await?;
+  Ok(())
+}
+
+// Kept after a confirmed change for as long as the cancel link sent to the old address lasts,
+// keyed by the new address so each cancel link can only undo its own change
+pub async fn store_previous_email(pool: &Pool, user_uuid: &Uuid, new_email: &str, previous: &PreviousEmail, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("email_change_previous:{}:{}", user_uuid, new_email);
+  let _: () = conn.set_ex(&key, serde_json::to_string(previous)?, expiration_time).await?;
+  Ok(())
+}
+
+// Returns None if no confirmed change to this address can be undone, or the cancel window is over
+pub async fn take_previous_email(pool: &Pool, user_uuid: &Uuid, new_email: &str) -> Result<Option<PreviousEmail>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("email_change_previous:{}:{}", user_uuid, new_email);
+  let previous: Option<String> = conn.get_del(&key).await?;
+  Ok(previous.map(|previous| serde_json::from_str(&previous)).transpose()?)
+}
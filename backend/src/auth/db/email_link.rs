@@ -0,0 +1,34 @@
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+pub async fn store_link(pool: &Pool, jti: &Uuid, purpose: &str, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("email_link:{}", jti);
+  let _: () = conn.set_ex(&key, purpose, expiration_time).await?;
+  Ok(())
+}
+
+// Returns true only for the first caller, so every link can be used once
+pub async fn consume_link(pool: &Pool, jti: &Uuid, purpose: &str) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("email_link:{}", jti);
+  let stored: Option<String> = conn.get_del(&key).await?;
+  Ok(stored.as_deref() == Some(purpose))
+}
+
+// Returns false if an email was already sent to this address in the last `cooldown` seconds
+pub async fn try_start_resend_cooldown(pool: &Pool, purpose: &str, email: &str, cooldown: u64) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("email_link_resend:{}:{}", purpose, email);
+  let set: Option<String> = deadpool_redis::redis::cmd("SET")
+    .arg(&key)
+    .arg(1)
+    .arg("NX")
+    .arg("EX")
+    .arg(cooldown)
+    .query_async(&mut conn)
+    .await?;
+  Ok(set.is_some())
+}
@@ -0,0 +1,19 @@
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+// Entries only need to outlive the token itself, after that the token is rejected as expired anyway
+pub async fn deny_token(pool: &Pool, jti: &Uuid, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("jwt_denylist:{}", jti);
+  let _: () = conn.set_ex(&key, 1, expiration_time.max(1)).await?;
+  Ok(())
+}
+
+pub async fn is_denied(pool: &Pool, jti: &Uuid) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("jwt_denylist:{}", jti);
+  let denied: bool = conn.exists(&key).await?;
+  Ok(denied)
+}
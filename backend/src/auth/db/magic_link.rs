@@ -0,0 +1,31 @@
+use serde::{Serialize, Deserialize};
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+pub struct MagicLink {
+  pub uuid: Uuid,
+  pub email: String,                     // Address the link was sent to
+  pub confirmation_code: Option<String>, // Set when the link is bound to the requesting browser
+}
+
+pub async fn store_link(pool: &Pool, token_hash: &str, link: &MagicLink, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("magic_link:{}", token_hash);
+  let json_str = serde_json::to_string(link)?;
+  let _: () = conn.set_ex(&key, &json_str, expiration_time).await?;
+  Ok(())
+}
+
+// Deletes the link on read, a wrong confirmation code uses the link up too
+pub async fn take_link(pool: &Pool, token_hash: &str) -> Result<Option<MagicLink>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("magic_link:{}", token_hash);
+  let json_str: Option<String> = conn.get_del(&key).await?;
+  match json_str {
+    Some(json_str) => Ok(Some(serde_json::from_str(&json_str)?)),
+    None => Ok(None),
+  }
+}
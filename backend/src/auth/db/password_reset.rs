@@ -0,0 +1,33 @@
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+// Only the token hash is stored, so a redis dump can't be used to reset passwords
+pub async fn store_reset_token(pool: &Pool, token_hash: &str, uuid: &Uuid, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("password_reset:{}", token_hash);
+  let _: () = conn.set_ex(&key, uuid.to_string(), expiration_time).await?;
+  Ok(())
+}
+
+// Looks the token up without using it, so a rejected password doesn't cost the user their link
+pub async fn get_reset_token(pool: &Pool, token_hash: &str) -> Result<Option<Uuid>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("password_reset:{}", token_hash);
+  let uuid: Option<String> = conn.get(&key).await?;
+  match uuid {
+    Some(uuid) => Ok(Some(Uuid::parse_str(&uuid)?)),
+    None => Ok(None),
+  }
+}
+
+pub async fn consume_reset_token(pool: &Pool, token_hash: &str) -> Result<Option<Uuid>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("password_reset:{}", token_hash);
+  let uuid: Option<String> = conn.get_del(&key).await?;
+  match uuid {
+    Some(uuid) => Ok(Some(Uuid::parse_str(&uuid)?)),
+    None => Ok(None),
+  }
+}
@@ -0,0 +1,38 @@
+use serde::{Serialize, Deserialize};
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+pub struct PendingPhoneChange {
+  pub user_uuid: Uuid,
+  pub new_phone_num: String,
+}
+
+// Keyed by a fresh flow id, the sms code for the new number is stored under the same id
+pub async fn store_pending_change(pool: &Pool, flow_id: &Uuid, change: &PendingPhoneChange, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("phone_change:{}", flow_id);
+  let json_str = serde_json::to_string(change)?;
+  let _: () = conn.set_ex(&key, &json_str, expiration_time).await?;
+  Ok(())
+}
+
+pub async fn get_pending_change(pool: &Pool, flow_id: &Uuid) -> Result<Option<PendingPhoneChange>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("phone_change:{}", flow_id);
+  let json_str: Option<String> = conn.get(&key).await?;
+  match json_str {
+    Some(json_str) => Ok(Some(serde_json::from_str(&json_str)?)),
+    None => Ok(None),
+  }
+}
+
+// Removes the flow and its code, returns false if another request got there first
+pub async fn take_pending_change(pool: &Pool, flow_id: &Uuid) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let change: Option<String> = conn.get_del(format!("phone_change:{}", flow_id)).await?;
+  let _: u32 = conn.del(format!("sms_code:{}", flow_id)).await?;
+  Ok(change.is_some())
+}
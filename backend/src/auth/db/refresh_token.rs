@@ -0,0 +1,84 @@
+use serde::{Serialize, Deserialize};
+use sqlx::types::Json;
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+// A family is every refresh token issued from a single sign-in on a single device.
+// Revoking the family signs that device out.
+#[derive(Serialize, Deserialize)]
+pub struct RefreshFamily {
+  pub user_uuid: Uuid,
+  pub token_version: u64,
+}
+
+pub enum RefreshTokenUse {
+  Valid(Uuid),   // First use, holds the family id
+  Reused(Uuid),  // Already rotated, holds the family id
+  Unknown,
+}
+
+pub async fn create_family(pool: &Pool, user_uuid: &Uuid, token_version: u64, expiration_time: u64) -> Result<Uuid, Error> {
+  let mut conn = pool.get().await?;
+  let family_id = Uuid::new_v4();
+  let key = format!("refresh_family:{}", family_id);
+  let json = Json(RefreshFamily {
+    user_uuid: *user_uuid,
+    token_version,
+  });
+  let _: () = conn.set_ex(&key, serde_json::to_string(&json)?, expiration_time).await?;
+  Ok(family_id)
+}
+
+pub async fn get_family(pool: &Pool, family_id: &Uuid) -> Result<Option<RefreshFamily>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("refresh_family:{}", family_id);
+  let json_str: Option<String> = conn.get(&key).await?;
+  match json_str {
+    Some(json_str) => Ok(Some(serde_json::from_str(&json_str)?)),
+    None => Ok(None),
+  }
+}
+
+pub async fn family_exists(pool: &Pool, family_id: &Uuid) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("refresh_family:{}", family_id);
+  let exists: bool = conn.exists(&key).await?;
+  Ok(exists)
+}
+
+pub async fn revoke_family(pool: &Pool, family_id: &Uuid) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("refresh_family:{}", family_id);
+  let _: () = conn.del(&key).await?;
+  Ok(())
+}
+
+// Stores a new token of the family and keeps the family alive as long as the device keeps refreshing
+pub async fn store_token(pool: &Pool, token_hash: &str, family_id: &Uuid, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("refresh_token:{}", token_hash);
+  let _: () = conn.set_ex(&key, family_id.to_string(), expiration_time).await?;
+  let _: () = conn.expire(format!("refresh_family:{}", family_id), expiration_time as i64).await?;
+  Ok(())
+}
+
+// Tokens are moved to a "used" key on first use, so a replayed token can be told apart from a made up one
+pub async fn use_token(pool: &Pool, token_hash: &str, expiration_time: u64) -> Result<RefreshTokenUse, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("refresh_token:{}", token_hash);
+  let used_key = format!("refresh_token_used:{}", token_hash);
+
+  let family_id: Option<String> = conn.get_del(&key).await?;
+  if let Some(family_id) = family_id {
+    let _: () = conn.set_ex(&used_key, &family_id, expiration_time).await?;
+    return Ok(RefreshTokenUse::Valid(Uuid::parse_str(&family_id)?));
+  }
+
+  let family_id: Option<String> = conn.get(&used_key).await?;
+  match family_id {
+    Some(family_id) => Ok(RefreshTokenUse::Reused(Uuid::parse_str(&family_id)?)),
+    None => Ok(RefreshTokenUse::Unknown),
+  }
+}
@@ -0,0 +1,79 @@
+use uuid::Uuid;
+use time::OffsetDateTime;
+use serde::{Serialize, Deserialize};
+use sqlx::{Error, FromRow, PgPool, query_as};
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct RoleAuditEntry {
+  pub actor_uuid: Uuid,
+  pub target_uuid: Uuid,
+  pub role: String,
+  pub action: String,
+  pub created_at: OffsetDateTime,
+}
+
+// Role changes and their audit rows are written in one transaction, so the log can't miss a change.
+// Returns false if the user doesn't exist or already had (or lacked) the role.
+pub async fn grant_role(pool: &PgPool, actor_uuid: &Uuid, target_uuid: &Uuid, role: &str) -> Result<bool, Error> {
+  let mut tx = pool.begin().await?;
+  let result = sqlx::query!(
+    "UPDATE users SET roles = array_append(roles, $1) WHERE uuid = $2 AND NOT ($1 = ANY(roles))",
+    role,
+    target_uuid,
+  )
+  .execute(&mut *tx)
+  .await?;
+  if result.rows_affected() == 0 {
+    return Ok(false);
+  }
+  insert_audit(&mut tx, actor_uuid, target_uuid, role, "grant").await?;
+  tx.commit().await?;
+  Ok(true)
+}
+
+pub async fn revoke_role(pool: &PgPool, actor_uuid: &Uuid, target_uuid: &Uuid, role: &str) -> Result<bool, Error> {
+  let mut tx = pool.begin().await?;
+  let result = sqlx::query!(
+    "UPDATE users SET roles = array_remove(roles, $1) WHERE uuid = $2 AND $1 = ANY(roles)",
+    role,
+    target_uuid,
+  )
+  .execute(&mut *tx)
+  .await?;
+  if result.rows_affected() == 0 {
+    return Ok(false);
+  }
+  insert_audit(&mut tx, actor_uuid, target_uuid, role, "revoke").await?;
+  tx.commit().await?;
+  Ok(true)
+}
+
+async fn insert_audit(tx: &mut sqlx::PgConnection, actor_uuid: &Uuid, target_uuid: &Uuid, role: &str, action: &str) -> Result<(), Error> {
+  sqlx::query!(
+    "INSERT INTO role_audit_log (actor_uuid, target_uuid, role, action) VALUES ($1, $2, $3, $4)",
+    actor_uuid,
+    target_uuid,
+    role,
+    action,
+  )
+  .execute(tx)
+  .await
+  .map(|_| ())
+}
+
+pub async fn get_audit_log_for_user(pool: &PgPool, target_uuid: &Uuid) -> Result<Vec<RoleAuditEntry>, Error> {
+  let entries = query_as!(
+    RoleAuditEntry,
+    r#"
+    SELECT actor_uuid, target_uuid, role, action, created_at
+    FROM role_audit_log
+    WHERE target_uuid = $1
+    ORDER BY created_at DESC
+    "#,
+    target_uuid
+  )
+  .fetch_all(pool)
+  .await?;
+
+  Ok(entries)
+}
@@ -0,0 +1,116 @@
+use uuid::Uuid;
+use time::OffsetDateTime;
+use serde::{Serialize, Deserialize};
+use sqlx::{Error, FromRow, PgPool, query_as};
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct SessionData {
+  pub id: Uuid,
+  pub user_uuid: Uuid,
+  pub method: String,
+  pub user_agent: Option<String>,
+  pub ip: Option<String>,
+  pub created_at: OffsetDateTime,
+  pub last_used_at: OffsetDateTime,
+  pub expires_at: OffsetDateTime,
+  pub revoked_at: Option<OffsetDateTime>,
+}
+
+pub async fn create_session(pool: &PgPool, id: &Uuid, user_uuid: &Uuid, method: &str, user_agent: Option<&str>, ip: Option<&str>, expiration_sec: i64) -> Result<(), Error> {
+  sqlx::query!(
+    r#"
+    INSERT INTO sessions (id, user_uuid, method, user_agent, ip, created_at, last_used_at, expires_at)
+    VALUES ($1, $2, $3, $4, $5, now(), now(), now() + make_interval(secs => $6))
+    "#,
+    id,
+    user_uuid,
+    method,
+    user_agent,
+    ip,
+    expiration_sec as f64,
+  )
+  .execute(pool)
+  .await
+  .map(|_| ())
+}
+
+pub async fn get_active_sessions(pool: &PgPool, user_uuid: &Uuid) -> Result<Vec<SessionData>, Error> {
+  let sessions = query_as!(
+    SessionData,
+    r#"
+    SELECT id, user_uuid, method, user_agent, ip, created_at, last_used_at, expires_at, revoked_at
+    FROM sessions
+    WHERE user_uuid = $1 AND revoked_at IS NULL AND expires_at > now()
+    ORDER BY last_used_at DESC
+    "#,
+    user_uuid
+  )
+  .fetch_all(pool)
+  .await?;
+
+  Ok(sessions)
+}
+
+pub async fn get_session(pool: &PgPool, id: &Uuid) -> Result<Option<SessionData>, Error> {
+  let session = query_as!(
+    SessionData,
+    r#"
+    SELECT id, user_uuid, method, user_agent, ip, created_at, last_used_at, expires_at, revoked_at
+    FROM sessions
+    WHERE id = $1
+    "#,
+    id
+  )
+  .fetch_optional(pool)
+  .await?;
+
+  Ok(session)
+}
+
+// Full sign in history, including revoked and expired sessions
+pub async fn get_all_sessions(pool: &PgPool, user_uuid: &Uuid) -> Result<Vec<SessionData>, Error> {
+  let sessions = query_as!(
+    SessionData,
+    r#"
+    SELECT id, user_uuid, method, user_agent, ip, created_at, last_used_at, expires_at, revoked_at
+    FROM sessions
+    WHERE user_uuid = $1
+    ORDER BY created_at DESC
+    "#,
+    user_uuid
+  )
+  .fetch_all(pool)
+  .await?;
+
+  Ok(sessions)
+}
+
+pub async fn touch_session(pool: &PgPool, id: &Uuid, expiration_sec: i64) -> Result<(), Error> {
+  sqlx::query!(
+    "UPDATE sessions SET last_used_at = now(), expires_at = now() + make_interval(secs => $2) WHERE id = $1",
+    id,
+    expiration_sec as f64,
+  )
+  .execute(pool)
+  .await
+  .map(|_| ())
+}
+
+// Returns false if the session doesn't exist, belongs to someone else or is already revoked
+pub async fn revoke_session(pool: &PgPool, user_uuid: &Uuid, id: &Uuid) -> Result<bool, Error> {
+  sqlx::query!(
+    "UPDATE sessions SET revoked_at = now() WHERE id = $1 AND user_uuid = $2 AND revoked_at IS NULL",
+    id,
+    user_uuid,
+  )
+  .execute(pool)
+  .await
+  .map(|result| result.rows_affected() == 1)
+}
+
+pub async fn revoke_all_sessions(pool: &PgPool, user_uuid: &Uuid) -> Result<(), Error> {
+  sqlx::query!("UPDATE sessions SET revoked_at = now() WHERE user_uuid = $1 AND revoked_at IS NULL", user_uuid)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
@@ -0,0 +1,85 @@
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+// Issued after the password was checked, while the second factor is still missing.
+// Only the token hash is stored, like reset tokens.
+pub async fn store_challenge(pool: &Pool, token_hash: &str, user_uuid: &Uuid, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("sign_in_challenge:{}", token_hash);
+  let _: () = conn.set_ex(&key, user_uuid.to_string(), expiration_time).await?;
+  Ok(())
+}
+
+pub async fn get_challenge(pool: &Pool, token_hash: &str) -> Result<Option<Uuid>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("sign_in_challenge:{}", token_hash);
+  let uuid: Option<String> = conn.get(&key).await?;
+  match uuid {
+    Some(uuid) => Ok(Some(Uuid::parse_str(&uuid)?)),
+    None => Ok(None),
+  }
+}
+
+// Returns false if another request already completed the challenge
+pub async fn consume_challenge(pool: &Pool, token_hash: &str) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("sign_in_challenge:{}", token_hash);
+  let uuid: Option<String> = conn.get_del(&key).await?;
+  Ok(uuid.is_some())
+}
+
+// Wrong codes burn the challenge after `max_attempts`, so the user has to enter the password again
+pub async fn record_failed_attempt(pool: &Pool, token_hash: &str, max_attempts: u32, expiration_time: i64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("sign_in_challenge_attempts:{}", token_hash);
+  let attempts: u32 = conn.incr(&key, 1).await?;
+  if attempts == 1 {
+    let _: u32 = conn.expire(&key, expiration_time).await?;
+  }
+  if attempts >= max_attempts {
+    let _: u32 = conn.del(format!("sign_in_challenge:{}", token_hash)).await?;
+  }
+  Ok(())
+}
+
+// Counted per user across challenges, since a correct password can always get a fresh challenge
+pub async fn record_second_factor_failure(pool: &Pool, user_uuid: &Uuid, expiration_time: i64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("second_factor_failures:{}", user_uuid);
+  let failures: u32 = conn.incr(&key, 1).await?;
+  if failures == 1 {
+    let _: u32 = conn.expire(&key, expiration_time).await?;
+  }
+  Ok(())
+}
+
+pub async fn is_second_factor_locked(pool: &Pool, user_uuid: &Uuid, max_failures: u32) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("second_factor_failures:{}", user_uuid);
+  let failures: Option<u32> = conn.get(&key).await?;
+  Ok(failures.unwrap_or(0) >= max_failures)
+}
+
+pub async fn clear_second_factor_failures(pool: &Pool, user_uuid: &Uuid) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("second_factor_failures:{}", user_uuid);
+  let _: u32 = conn.del(&key).await?;
+  Ok(())
+}
+
+// TOTP codes stay valid for a whole step, this keeps each one to a single use
+pub async fn try_use_totp_code(pool: &Pool, user_uuid: &Uuid, code: &str, expiration_time: u64) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("totp_used:{}:{}", user_uuid, code);
+  let set: Option<String> = deadpool_redis::redis::cmd("SET")
+    .arg(&key)
+    .arg(1)
+    .arg("NX")
+    .arg("EX")
+    .arg(expiration_time)
+    .query_async(&mut conn)
+    .await?;
+  Ok(set.is_some())
+}
@@ -11,6 +11,7 @@ use crate::auth::google_claims::GoogleClaims;
 #[derive(Serialize, Deserialize)]
 pub struct SignUpSession {
   pub email: String,
+  pub email_verified: bool,
   pub name: String,
   pub password_hash: Option<String>,
   pub google_sub: Option<String>,
@@ -26,6 +27,7 @@ pub async fn start_sign_up_password(pool: &Pool, email: &str, password_hash: &st
   let key = format!("sign_up_session:{}", uuid);
   let json = Json(SignUpSession {
     email: email.to_string(),
+    email_verified: false,
     name: name.to_string(),
     password_hash: Some(password_hash.to_string()),
     google_sub: None,
@@ -44,6 +46,7 @@ pub async fn start_sign_up_google(pool: &Pool, claims: &GoogleClaims, expiration
   let key = format!("sign_up_session:{}", uuid);
   let json = Json(SignUpSession {
     email: claims.email.clone(),
+    email_verified: claims.email_verified,
     name: claims.name.clone(),
     password_hash: None,
     google_sub: Some(claims.sub.to_string()),
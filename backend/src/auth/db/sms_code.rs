@@ -58,3 +58,18 @@ pub async fn get_code_attempts_count(pool: &Pool, uuid: &Uuid) -> Result<u32, Er
   let code_struct: SmsCode = serde_json::from_str(&json_str)?;
   return Ok(code_struct.attempts_count);
 }
+
+// Returns false if a code was already sent to this number in the last `cooldown` seconds, shared by every flow that texts a user
+pub async fn try_start_resend_cooldown(pool: &Pool, phone_num: &str, cooldown: u64) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("sms_resend:{}", phone_num);
+  let set: Option<String> = deadpool_redis::redis::cmd("SET")
+    .arg(&key)
+    .arg(1)
+    .arg("NX")
+    .arg("EX")
+    .arg(cooldown)
+    .query_async(&mut conn)
+    .await?;
+  Ok(set.is_some())
+}
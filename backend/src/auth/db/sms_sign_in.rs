@@ -0,0 +1,32 @@
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+// The code is stored under a random request id rather than the user uuid, so the
+// response to a code request is the same whether or not the number has an account
+
+pub async fn store_request(pool: &Pool, request_id: &Uuid, user_uuid: &Uuid, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("sms_sign_in:{}", request_id);
+  let _: () = conn.set_ex(&key, user_uuid.to_string(), expiration_time).await?;
+  Ok(())
+}
+
+pub async fn get_request(pool: &Pool, request_id: &Uuid) -> Result<Option<Uuid>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("sms_sign_in:{}", request_id);
+  let uuid: Option<String> = conn.get(&key).await?;
+  match uuid {
+    Some(uuid) => Ok(Some(Uuid::parse_str(&uuid)?)),
+    None => Ok(None),
+  }
+}
+
+// Removes the request and its code once used, returns false if another request got there first
+pub async fn consume_request(pool: &Pool, request_id: &Uuid) -> Result<bool, Error> {
+  let mut conn = pool.get().await?;
+  let uuid: Option<String> = conn.get_del(format!("sms_sign_in:{}", request_id)).await?;
+  let _: u32 = conn.del(format!("sms_code:{}", request_id)).await?;
+  Ok(uuid.is_some())
+}
@@ -0,0 +1,20 @@
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+
+// Every JWT carries the user's token version from when it was issued.
+// Bumping the version invalidates all of the user's previously issued tokens.
+pub async fn get_token_version(pool: &Pool, uuid: &Uuid) -> Result<u64, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("token_version:{}", uuid);
+  let version: Option<u64> = conn.get(&key).await?;
+  Ok(version.unwrap_or(0))
+}
+
+pub async fn increment_token_version(pool: &Pool, uuid: &Uuid) -> Result<u64, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("token_version:{}", uuid);
+  let version: u64 = conn.incr(&key, 1).await?;
+  Ok(version)
+}
@@ -0,0 +1,91 @@
+use uuid::Uuid;
+use time::OffsetDateTime;
+use serde::{Serialize, Deserialize};
+use sqlx::{Error, FromRow, PgPool, query_as};
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct TotpFactor {
+  pub user_uuid: Uuid,
+  pub secret_encrypted: String,
+  pub enabled_at: Option<OffsetDateTime>,
+  pub created_at: OffsetDateTime,
+}
+
+pub async fn get_factor(pool: &PgPool, user_uuid: &Uuid) -> Result<Option<TotpFactor>, Error> {
+  let factor = query_as!(
+    TotpFactor,
+    r#"
+    SELECT user_uuid, secret_encrypted, enabled_at, created_at
+    FROM totp_factors
+    WHERE user_uuid = $1
+    "#,
+    user_uuid
+  )
+  .fetch_optional(pool)
+  .await?;
+
+  Ok(factor)
+}
+
+// Replaces an unconfirmed secret, returns false if TOTP is already enabled
+pub async fn store_pending_factor(pool: &PgPool, user_uuid: &Uuid, secret_encrypted: &str) -> Result<bool, Error> {
+  sqlx::query!(
+    r#"
+    INSERT INTO totp_factors (user_uuid, secret_encrypted, created_at)
+    VALUES ($1, $2, now())
+    ON CONFLICT (user_uuid) DO UPDATE SET secret_encrypted = EXCLUDED.secret_encrypted, created_at = now()
+    WHERE totp_factors.enabled_at IS NULL
+    "#,
+    user_uuid,
+    secret_encrypted,
+  )
+  .execute(pool)
+  .await
+  .map(|result| result.rows_affected() == 1)
+}
+
+// Enables the factor and replaces the recovery codes in one go, returns false if it was already enabled
+pub async fn enable_factor(pool: &PgPool, user_uuid: &Uuid, recovery_code_hashes: &[String]) -> Result<bool, Error> {
+  let mut tx = pool.begin().await?;
+  let result = sqlx::query!("UPDATE totp_factors SET enabled_at = now() WHERE user_uuid = $1 AND enabled_at IS NULL", user_uuid)
+    .execute(&mut *tx)
+    .await?;
+  if result.rows_affected() == 0 {
+    return Ok(false);
+  }
+  sqlx::query!("DELETE FROM totp_recovery_codes WHERE user_uuid = $1", user_uuid)
+    .execute(&mut *tx)
+    .await?;
+  sqlx::query!(
+    "INSERT INTO totp_recovery_codes (user_uuid, code_hash) SELECT $1, UNNEST($2::text[])",
+    user_uuid,
+    recovery_code_hashes,
+  )
+  .execute(&mut *tx)
+  .await?;
+  tx.commit().await?;
+  Ok(true)
+}
+
+pub async fn delete_factor(pool: &PgPool, user_uuid: &Uuid) -> Result<(), Error> {
+  let mut tx = pool.begin().await?;
+  sqlx::query!("DELETE FROM totp_recovery_codes WHERE user_uuid = $1", user_uuid)
+    .execute(&mut *tx)
+    .await?;
+  sqlx::query!("DELETE FROM totp_factors WHERE user_uuid = $1", user_uuid)
+    .execute(&mut *tx)
+    .await?;
+  tx.commit().await
+}
+
+// Marks the code used in the same statement that checks it, so it works only once
+pub async fn use_recovery_code(pool: &PgPool, user_uuid: &Uuid, code_hash: &str) -> Result<bool, Error> {
+  sqlx::query!(
+    "UPDATE totp_recovery_codes SET used_at = now() WHERE user_uuid = $1 AND code_hash = $2 AND used_at IS NULL",
+    user_uuid,
+    code_hash,
+  )
+  .execute(pool)
+  .await
+  .map(|result| result.rows_affected() >= 1)
+}
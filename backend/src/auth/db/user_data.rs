@@ -11,19 +11,22 @@ pub struct UserData {
   pub email: String,
   pub email_verified: bool,
   pub name: String,
+  // Left out of every response the user is sent in
+  #[serde(skip_serializing)]
   pub password_hash: Option<String>,
   pub google_sub: Option<String>,
   pub phone_num: String,
   pub created_at: OffsetDateTime,
   pub last_seen_at: Option<OffsetDateTime>,
   pub picture: Option<String>,
+  pub roles: Vec<String>,
 }
 
 pub async fn get_user_by_uuid(pool: &PgPool, uuid: &Uuid) -> Result<Option<UserData>, Error> {
   let user = query_as!(
     UserData,
     r#"
-    SELECT uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture
+    SELECT uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture, roles
     FROM users
     WHERE uuid = $1
     "#,
@@ -39,7 +42,7 @@ pub async fn get_user_by_email(pool: &PgPool, email: &str) -> Result<Option<User
   let user = query_as!(
     UserData,
     r#"
-    SELECT uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture
+    SELECT uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture, roles
     FROM users
     WHERE email = $1
     "#,
@@ -51,11 +54,27 @@ pub async fn get_user_by_email(pool: &PgPool, email: &str) -> Result<Option<User
   Ok(user)
 }
 
+pub async fn get_user_by_phone(pool: &PgPool, phone_num: &str) -> Result<Option<UserData>, Error> {
+  let user = query_as!(
+    UserData,
+    r#"
+    SELECT uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture, roles
+    FROM users
+    WHERE phone_num = $1
+    "#,
+    phone_num
+  )
+  .fetch_optional(pool)
+  .await?;
+
+  Ok(user)
+}
+
 pub async fn get_user_by_google_sub(pool: &PgPool, sub: &str) -> Result<Option<UserData>, Error> {
   let user = query_as!(
     UserData,
     r#"
-    SELECT uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture
+    SELECT uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture, roles
     FROM users
     WHERE google_sub = $1
     "#,
@@ -74,6 +93,111 @@ pub async fn link_google_sub(pool: &PgPool, uuid: &Uuid, sub: &str) -> Result<()
     .map(|_| ())
 }
 
+// Returns false if the account already has a password, so this can't be used to overwrite one
+pub async fn link_password(pool: &PgPool, uuid: &Uuid, password_hash: &str) -> Result<bool, Error> {
+  sqlx::query!("UPDATE users SET password_hash = $1 WHERE uuid = $2 AND password_hash IS NULL", password_hash, uuid)
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() == 1)
+}
+
+// Only unlinks when a password is left to sign in with, checked in the same statement so it can't race link_password
+pub async fn unlink_google_sub(pool: &PgPool, uuid: &Uuid) -> Result<bool, Error> {
+  sqlx::query!("UPDATE users SET google_sub = NULL WHERE uuid = $1 AND google_sub IS NOT NULL AND password_hash IS NOT NULL", uuid)
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() == 1)
+}
+
+// The new address was confirmed through a link sent to it, so it's verified right away.
+// Fails with a unique violation if another account took the address in the meantime.
+pub async fn change_email(pool: &PgPool, uuid: &Uuid, new_email: &str) -> Result<(), Error> {
+  sqlx::query!("UPDATE users SET email = $1, email_verified = true WHERE uuid = $2", new_email, uuid)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+// Undoes a confirmed email change, only while the account still has the address it was changed to
+pub async fn restore_email(pool: &PgPool, uuid: &Uuid, changed_email: &str, previous_email: &str, previous_verified: bool) -> Result<bool, Error> {
+  sqlx::query!("UPDATE users SET email = $1, email_verified = $2 WHERE uuid = $3 AND email = $4", previous_email, previous_verified, uuid, changed_email)
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() == 1)
+}
+
+pub async fn change_phone_num(pool: &PgPool, uuid: &Uuid, new_phone_num: &str) -> Result<(), Error> {
+  sqlx::query!("UPDATE users SET phone_num = $1 WHERE uuid = $2", new_phone_num, uuid)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+// Only name and picture are editable by the user, everything else has its own verified flow
+pub async fn update_profile(pool: &PgPool, uuid: &Uuid, name: &str, picture: Option<&str>) -> Result<Option<UserData>, Error> {
+  let user = query_as!(
+    UserData,
+    r#"
+    UPDATE users SET name = $2, picture = $3
+    WHERE uuid = $1
+    RETURNING uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture, roles
+    "#,
+    uuid,
+    name,
+    picture,
+  )
+  .fetch_optional(pool)
+  .await?;
+
+  Ok(user)
+}
+
+pub async fn schedule_deletion(pool: &PgPool, uuid: &Uuid, grace_sec: i64) -> Result<OffsetDateTime, Error> {
+  let row = sqlx::query!(
+    r#"
+    UPDATE users SET deletion_scheduled_at = now() + make_interval(secs => $2)
+    WHERE uuid = $1
+    RETURNING deletion_scheduled_at as "deletion_scheduled_at!"
+    "#,
+    uuid,
+    grace_sec as f64,
+  )
+  .fetch_one(pool)
+  .await?;
+
+  Ok(row.deletion_scheduled_at)
+}
+
+// Returns true if a scheduled deletion was cancelled
+pub async fn cancel_deletion(pool: &PgPool, uuid: &Uuid) -> Result<bool, Error> {
+  sqlx::query!("UPDATE users SET deletion_scheduled_at = NULL WHERE uuid = $1 AND deletion_scheduled_at IS NOT NULL", uuid)
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() == 1)
+}
+
+// Hard delete, sessions go with the row and the email and phone become free to sign up with again.
+// role_audit_log has no foreign key, so in the same transaction the user's own role history is
+// deleted and their uuid is replaced with the nil uuid where they were the admin making the change.
+pub async fn purge_deleted_users(pool: &PgPool) -> Result<u64, Error> {
+  let mut tx = pool.begin().await?;
+  let purged: Vec<Uuid> = sqlx::query_scalar!("DELETE FROM users WHERE deletion_scheduled_at <= now() RETURNING uuid")
+    .fetch_all(&mut *tx)
+    .await?;
+  if purged.is_empty() {
+    return Ok(0);
+  }
+
+  sqlx::query!("DELETE FROM role_audit_log WHERE target_uuid = ANY($1)", &purged)
+    .execute(&mut *tx)
+    .await?;
+  sqlx::query!("UPDATE role_audit_log SET actor_uuid = $1 WHERE actor_uuid = ANY($2)", Uuid::nil(), &purged)
+    .execute(&mut *tx)
+    .await?;
+  tx.commit().await?;
+  Ok(purged.len() as u64)
+}
+
 pub async fn update_last_seen(pool: &PgPool, uuid: &Uuid) -> Result<(), Error> {
   sqlx::query!("UPDATE users SET last_seen_at = now() WHERE uuid = $1", uuid)
     .execute(pool)
@@ -81,16 +205,31 @@ pub async fn update_last_seen(pool: &PgPool, uuid: &Uuid) -> Result<(), Error> {
     .map(|_| ())
 }
 
+pub async fn verify_email(pool: &PgPool, uuid: &Uuid, email: &str) -> Result<bool, Error> {
+  sqlx::query!("UPDATE users SET email_verified = true WHERE uuid = $1 AND email = $2", uuid, email)
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() == 1)
+}
+
+pub async fn change_password(pool: &PgPool, uuid: &Uuid, password_hash: &str) -> Result<(), Error> {
+  sqlx::query!("UPDATE users SET password_hash = $1 WHERE uuid = $2", password_hash, uuid)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
 pub async fn create_user(pool: &PgPool, session: &SignUpSession) -> Result<UserData, Error> {
   let user = sqlx::query_as!(
     UserData,
     r#"
     INSERT INTO users (uuid, email, email_verified, name, password_hash, google_sub, phone_num, created_at, last_seen_at, picture)
-    VALUES ($1, $2, false, $3, $4, $5, $6, now(), now(), $7)
-    RETURNING uuid, email, password_hash, email_verified, name, google_sub, phone_num, created_at, last_seen_at, picture
+    VALUES ($1, $2, $3, $4, $5, $6, $7, now(), now(), $8)
+    RETURNING uuid, email, password_hash, email_verified, name, google_sub, phone_num, created_at, last_seen_at, picture, roles
     "#,
     Uuid::new_v4(),
     session.email,
+    session.email_verified,
     session.name,
     session.password_hash,
     session.google_sub,
@@ -103,4 +242,27 @@ pub async fn create_user(pool: &PgPool, session: &SignUpSession) -> Result<UserD
   Ok(user)
 }
 
-// TODO: Add link_picture, verify_email, change_password, update_last_seen, change_email, link_password
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn password_hash_is_never_serialized() {
+    let user = UserData {
+      uuid: Uuid::new_v4(),
+      email: "dana@example.com".to_string(),
+      email_verified: true,
+      name: "Dana Cohen".to_string(),
+      password_hash: Some("$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".to_string()),
+      google_sub: None,
+      phone_num: "+972522345678".to_string(),
+      created_at: OffsetDateTime::now_utc(),
+      last_seen_at: None,
+      picture: None,
+      roles: Vec::new(),
+    };
+    let json = serde_json::to_value(&user).unwrap();
+    assert!(json.get("password_hash").is_none());
+    assert_eq!(json["email"], "dana@example.com");
+  }
+}
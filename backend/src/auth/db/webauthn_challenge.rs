@@ -0,0 +1,42 @@
+use deadpool_redis::Pool;
+use deadpool_redis::redis::AsyncCommands;
+use anyhow::Error;
+use uuid::Uuid;
+use webauthn_rs::prelude::{DiscoverableAuthentication, PasskeyRegistration};
+
+// Ceremony states hold the challenge, they live in redis until the browser answers
+
+pub async fn store_registration(pool: &Pool, user_uuid: &Uuid, state: &PasskeyRegistration, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("webauthn_registration:{}", user_uuid);
+  let _: () = conn.set_ex(&key, serde_json::to_string(state)?, expiration_time).await?;
+  Ok(())
+}
+
+pub async fn take_registration(pool: &Pool, user_uuid: &Uuid) -> Result<Option<PasskeyRegistration>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("webauthn_registration:{}", user_uuid);
+  let state: Option<String> = conn.get_del(&key).await?;
+  match state {
+    Some(state) => Ok(Some(serde_json::from_str(&state)?)),
+    None => Ok(None),
+  }
+}
+
+pub async fn store_authentication(pool: &Pool, challenge_id: &Uuid, state: &DiscoverableAuthentication, expiration_time: u64) -> Result<(), Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("webauthn_authentication:{}", challenge_id);
+  let _: () = conn.set_ex(&key, serde_json::to_string(state)?, expiration_time).await?;
+  Ok(())
+}
+
+// Single use, a replayed assertion finds nothing
+pub async fn take_authentication(pool: &Pool, challenge_id: &Uuid) -> Result<Option<DiscoverableAuthentication>, Error> {
+  let mut conn = pool.get().await?;
+  let key = format!("webauthn_authentication:{}", challenge_id);
+  let state: Option<String> = conn.get_del(&key).await?;
+  match state {
+    Some(state) => Ok(Some(serde_json::from_str(&state)?)),
+    None => Ok(None),
+  }
+}
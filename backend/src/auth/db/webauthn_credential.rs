@@ -0,0 +1,97 @@
+use uuid::Uuid;
+use time::OffsetDateTime;
+use serde::{Serialize, Deserialize};
+use sqlx::{Error, FromRow, PgPool, query_as};
+
+#[derive(Serialize, Deserialize, FromRow)]
+pub struct WebauthnCredential {
+  pub credential_id: String,
+  pub user_uuid: Uuid,
+  pub name: String,
+  pub passkey: String, // Serialized webauthn_rs Passkey
+  pub created_at: OffsetDateTime,
+  pub last_used_at: Option<OffsetDateTime>,
+}
+
+pub async fn get_credentials(pool: &PgPool, user_uuid: &Uuid) -> Result<Vec<WebauthnCredential>, Error> {
+  let credentials = query_as!(
+    WebauthnCredential,
+    r#"
+    SELECT credential_id, user_uuid, name, passkey, created_at, last_used_at
+    FROM webauthn_credentials
+    WHERE user_uuid = $1
+    ORDER BY created_at
+    "#,
+    user_uuid
+  )
+  .fetch_all(pool)
+  .await?;
+
+  Ok(credentials)
+}
+
+// Scoped to the user, so an assertion can't pick someone else's credential
+pub async fn get_credential(pool: &PgPool, user_uuid: &Uuid, credential_id: &str) -> Result<Option<WebauthnCredential>, Error> {
+  let credential = query_as!(
+    WebauthnCredential,
+    r#"
+    SELECT credential_id, user_uuid, name, passkey, created_at, last_used_at
+    FROM webauthn_credentials
+    WHERE user_uuid = $1 AND credential_id = $2
+    "#,
+    user_uuid,
+    credential_id,
+  )
+  .fetch_optional(pool)
+  .await?;
+
+  Ok(credential)
+}
+
+pub async fn create_credential(pool: &PgPool, credential_id: &str, user_uuid: &Uuid, name: &str, passkey: &str) -> Result<(), Error> {
+  sqlx::query!(
+    "INSERT INTO webauthn_credentials (credential_id, user_uuid, name, passkey, created_at) VALUES ($1, $2, $3, $4, now())",
+    credential_id,
+    user_uuid,
+    name,
+    passkey,
+  )
+  .execute(pool)
+  .await
+  .map(|_| ())
+}
+
+// Stores the new signature counter along with the sign in time
+pub async fn record_use(pool: &PgPool, credential_id: &str, passkey: &str) -> Result<(), Error> {
+  sqlx::query!(
+    "UPDATE webauthn_credentials SET passkey = $2, last_used_at = now() WHERE credential_id = $1",
+    credential_id,
+    passkey,
+  )
+  .execute(pool)
+  .await
+  .map(|_| ())
+}
+
+pub async fn rename_credential(pool: &PgPool, user_uuid: &Uuid, credential_id: &str, name: &str) -> Result<bool, Error> {
+  sqlx::query!(
+    "UPDATE webauthn_credentials SET name = $3 WHERE user_uuid = $1 AND credential_id = $2",
+    user_uuid,
+    credential_id,
+    name,
+  )
+  .execute(pool)
+  .await
+  .map(|result| result.rows_affected() == 1)
+}
+
+pub async fn delete_credential(pool: &PgPool, user_uuid: &Uuid, credential_id: &str) -> Result<bool, Error> {
+  sqlx::query!(
+    "DELETE FROM webauthn_credentials WHERE user_uuid = $1 AND credential_id = $2",
+    user_uuid,
+    credential_id,
+  )
+  .execute(pool)
+  .await
+  .map(|result| result.rows_affected() == 1)
+}
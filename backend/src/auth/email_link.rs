@@ -0,0 +1,64 @@
+use jsonwebtoken::{encode, decode, Header, EncodingKey, DecodingKey, Validation, Algorithm};
+use deadpool_redis::Pool;
+use uuid::Uuid;
+use serde::{Serialize, Deserialize};
+use time::OffsetDateTime;
+
+use crate::auth::db::email_link;
+
+pub const VERIFY_EMAIL: &str = "verify_email";
+pub const CHANGE_EMAIL: &str = "change_email";
+pub const CANCEL_EMAIL_CHANGE: &str = "cancel_email_change";
+
+#[derive(Serialize, Deserialize)]
+pub struct EmailLinkClaims {
+  pub sub: Uuid,        // User UUID
+  pub email: String,    // Address the link is about: the one to verify, or the new one for email changes
+  pub purpose: String,  // What the link is allowed to do
+  pub jti: Uuid,        // Single-use id, kept in redis until consumed
+  pub exp: i64,
+  pub iat: i64,
+}
+
+#[derive(Debug)]
+pub enum EmailLinkError {
+  InvalidToken,
+  AlreadyUsed,
+  InternalError,
+}
+
+pub async fn create_link_token(pool: &Pool, secret: &str, purpose: &str, user_uuid: &Uuid, email: &str, expiration_sec: u64) -> Result<String, EmailLinkError> {
+  let now = OffsetDateTime::now_utc().unix_timestamp();
+  let claims = EmailLinkClaims {
+    sub: *user_uuid,
+    email: email.to_string(),
+    purpose: purpose.to_string(),
+    jti: Uuid::new_v4(),
+    exp: now + expiration_sec as i64,
+    iat: now,
+  };
+
+  let token = encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret.as_bytes()))
+    .map_err(|_| EmailLinkError::InternalError)?;
+  email_link::store_link(pool, &claims.jti, purpose, expiration_sec).await
+    .map_err(|_| EmailLinkError::InternalError)?;
+  Ok(token)
+}
+
+pub async fn consume_link_token(pool: &Pool, secret: &str, purpose: &str, token: &str) -> Result<EmailLinkClaims, EmailLinkError> {
+  let claims = decode::<EmailLinkClaims>(token, &DecodingKey::from_secret(secret.as_bytes()), &Validation::new(Algorithm::HS256))
+    .map_err(|_| EmailLinkError::InvalidToken)?
+    .claims;
+  if claims.purpose != purpose {
+    return Err(EmailLinkError::InvalidToken);
+  }
+  match email_link::consume_link(pool, &claims.jti, purpose).await {
+    Ok(true) => Ok(claims),
+    Ok(false) => Err(EmailLinkError::AlreadyUsed),
+    Err(_) => Err(EmailLinkError::InternalError),
+  }
+}
+
+pub fn link_url(base_url: &str, page: &str, token: &str) -> String {
+  format!("{}/{}?token={}", base_url.trim_end_matches('/'), page, token)
+}
@@ -1,6 +1,6 @@
 use google_jwt_verify::{ Client, Error };
-use tracing; // Make sure you have tracing or another logging crate setup
-use chrono::prelude::*;
+
+use crate::redact::{Email, Token};
 
 pub struct GoogleClaims {
     pub sub: String,
@@ -13,16 +13,15 @@ pub struct GoogleClaims {
 
 pub async fn get_google_claims(token_id: &str, client_id: &str) -> Result<GoogleClaims, Error> {
     let client = Client::new(client_id);
-    let local_now = Local::now();
-    println!("Current local time: {}", local_now);
-    
-    tracing::info!("Using Google client ID: {}", client_id);
-    tracing::info!("token is: {}", token_id);
 
     let token = match client.verify_id_token(token_id) {
         Ok(token) => token,
         Err(e) => {
-            tracing::error!("Failed to verify Google ID token: {:?}", e);   
+            tracing::warn!(
+                event = "google_token_verify_failure",
+                id_token = %Token(token_id),
+                reason = ?e,
+            );
             return Err(e);
         }
     };
@@ -32,7 +31,7 @@ pub async fn get_google_claims(token_id: &str, client_id: &str) -> Result<Google
     let name = token.get_payload().get_name();
     let email_verified = token.get_payload().is_email_verified();
 
-    tracing::info!(email, name);
+    tracing::debug!(event = "google_claims_verified", sub, email = %Email(&email));
 
     let picture_raw = token.get_payload().get_picture_url();
     let picture = if picture_raw.is_empty() {
@@ -1,17 +1,40 @@
 use argon2::{Argon2, PasswordHash, PasswordVerifier, PasswordHasher};
 use argon2::password_hash::{SaltString, rand_core::OsRng, Error};
+use rand::Rng;
+use sha2::{Sha256, Digest};
+use std::time::Instant;
+
+use crate::telemetry;
 
 pub fn hash_password(password: &str) -> Result<String, Error> {
     let salt = SaltString::generate(&mut OsRng);
 
+    let started = Instant::now();
     let password_hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+    telemetry::record_argon2("hash", started);
 
     Ok(password_hash.to_string())
 }
 
 pub fn verify_password(password: &str, password_hash: &str) -> bool {
   match PasswordHash::new(password_hash) {
-    Ok(parsed_hash) => Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok(),
+    Ok(parsed_hash) => {
+      let started = Instant::now();
+      let verified = Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok();
+      telemetry::record_argon2("verify", started);
+      verified
+    },
     Err(_) => false,
   }
 }
+
+// Opaque random token, safe to put in links and headers
+pub fn generate_token() -> String {
+  let bytes: [u8; 32] = rand::rng().random();
+  hex::encode(bytes)
+}
+
+// Tokens are random enough that a fast hash is fine for storing them at rest
+pub fn hash_token(token: &str) -> String {
+  hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -1,67 +1,115 @@
-use jsonwebtoken::{encode, decode, Header, EncodingKey, DecodingKey, Validation, Algorithm};
+use jsonwebtoken::{encode, decode, decode_header, Header, Validation};
+use jsonwebtoken::errors::ErrorKind;
 use sqlx::PgPool;
+use deadpool_redis::Pool;
 use uuid::Uuid;
 use serde::{Serialize, Deserialize};
 use time::OffsetDateTime;
 
+use crate::auth::jwt_keys::JwtKeys;
+use crate::auth::roles::Role;
 use crate::auth::db::user_data::{self, UserData};
+use crate::auth::db::token_version;
+use crate::auth::db::jwt_denylist;
+use crate::auth::db::refresh_token;
 
 #[derive(Serialize, Deserialize)]
 pub struct JWTClaims {
   pub sub: String, // Subject (user UUID as string)
   pub exp: i64,    // Expiration timestamp (as Unix time)
   pub iat: i64,    // Issued at (as Unix time)
+  #[serde(default)]
+  pub ver: u64,    // User's token version at issue time
+  pub sid: Uuid,   // Sign-in session (refresh token family) the token belongs to
+  pub jti: Uuid,   // Unique token id, used to deny a single token on logout
+  #[serde(default)]
+  pub roles: Vec<Role>, // User's roles at issue time
 }
 
 pub enum JWTError {
   EncodingError,
   DecodingError,
   InvalidToken,
+  ExpiredToken,
+  RevokedToken,
   UserNotFound,
   InternalError,
 }
 
-pub fn create_jwt_token(user_uuid: &Uuid, jwt_secret: &str, expiration_days: i64) -> Result<String, JWTError> {
+pub fn create_jwt_token(user_uuid: &Uuid, session_id: &Uuid, token_version: u64, roles: &[Role], keys: &JwtKeys, expiration_sec: i64) -> Result<String, JWTError> {
   let now = OffsetDateTime::now_utc().unix_timestamp();
 
   let claims = JWTClaims {
     sub: user_uuid.to_string(),
-    exp: now + 60 * 60 * 24 * expiration_days,
+    exp: now + expiration_sec,
     iat: now,
+    ver: token_version,
+    sid: *session_id,
+    jti: Uuid::new_v4(),
+    roles: roles.to_vec(),
   };
 
-  let mut header = Header::default();
-  header.alg = Algorithm::HS256;
-  
-  if let Ok(token) = encode(&header, &claims, &EncodingKey::from_secret(jwt_secret.as_bytes())) {
+  let mut header = Header::new(keys.signing_key.algorithm);
+  header.kid = Some(keys.signing_key.kid.clone());
+
+  if let Ok(token) = encode(&header, &claims, &keys.signing_key.encoding_key) {
     return Ok(token);
   }
   Err(JWTError::EncodingError)
 }
 
-pub fn get_jwt_claims(token: &str, secret: &str) -> Result<JWTClaims, JWTError> {
-  let validation = Validation::new(Algorithm::HS256);
-  if let Ok(token_data) = decode::<JWTClaims>(
+pub fn get_jwt_claims(token: &str, keys: &JwtKeys) -> Result<JWTClaims, JWTError> {
+  // The kid picks the key, and the key (not the token) decides the algorithm
+  let kid = decode_header(token).ok().and_then(|header| header.kid).ok_or(JWTError::InvalidToken)?;
+  let key = keys.verifying_keys.get(&kid).ok_or(JWTError::InvalidToken)?;
+  let validation = Validation::new(key.algorithm);
+  match decode::<JWTClaims>(
     token,
-    &DecodingKey::from_secret(secret.as_bytes()),
+    &key.decoding_key,
     &validation
   ) {
-    let now = OffsetDateTime::now_utc().unix_timestamp();
-    if token_data.claims.iat > now {
-      return Err(JWTError::InvalidToken);
-    }
-    return Ok(token_data.claims);
+    Ok(token_data) => {
+      let now = OffsetDateTime::now_utc().unix_timestamp();
+      if token_data.claims.iat > now {
+        return Err(JWTError::InvalidToken);
+      }
+      Ok(token_data.claims)
+    },
+    Err(error) if *error.kind() == ErrorKind::ExpiredSignature => Err(JWTError::ExpiredToken),
+    Err(_) => Err(JWTError::DecodingError),
   }
- Err(JWTError::DecodingError)
 }
 
-pub async fn verify_jwt_token(pool: &PgPool, token: &str, secret: &str) -> Result<UserData, JWTError> {
-  match get_jwt_claims(token, secret) {
+// Checks the signature and that the token wasn't revoked since it was issued.
+// Revocations live in redis, so they apply on every instance right away.
+pub async fn verify_jwt_claims(redis_pool: &Pool, token: &str, keys: &JwtKeys) -> Result<JWTClaims, JWTError> {
+  let claims = get_jwt_claims(token, keys)?;
+  let uuid = Uuid::parse_str(&claims.sub).map_err(|_| JWTError::InvalidToken)?;
+  match jwt_denylist::is_denied(redis_pool, &claims.jti).await {
+    Ok(true) => return Err(JWTError::RevokedToken),
+    Ok(false) => {},
+    Err(_) => return Err(JWTError::InternalError),
+  }
+  // The session is gone once its refresh token family is revoked
+  match refresh_token::family_exists(redis_pool, &claims.sid).await {
+    Ok(true) => {},
+    Ok(false) => return Err(JWTError::RevokedToken),
+    Err(_) => return Err(JWTError::InternalError),
+  }
+  match token_version::get_token_version(redis_pool, &uuid).await {
+    Ok(version) if claims.ver < version => Err(JWTError::RevokedToken),
+    Ok(_) => Ok(claims),
+    Err(_) => Err(JWTError::InternalError),
+  }
+}
+
+pub async fn verify_jwt_token(pool: &PgPool, redis_pool: &Pool, token: &str, keys: &JwtKeys) -> Result<(JWTClaims, UserData), JWTError> {
+  match verify_jwt_claims(redis_pool, token, keys).await {
     Ok(claims) => {
       if let Ok(uuid) = Uuid::parse_str(&claims.sub) {
         if let Ok(user_data) = user_data::get_user_by_uuid(pool, &uuid).await {
           if let Some(user_data) = user_data {
-            return Ok(user_data);
+            return Ok((claims, user_data));
           }
           return Err(JWTError::UserNotFound);
         }
@@ -73,3 +121,67 @@ pub async fn verify_jwt_token(pool: &PgPool, token: &str, secret: &str) -> Resul
   }
 }
 
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use crate::auth::jwt_keys::{load_jwt_keys, write_test_key};
+
+  // Each entry is (kid, seed, private)
+  fn load_keys(keys: &[(&str, u8, bool)], active_kid: &str) -> JwtKeys {
+    let dir = std::env::temp_dir().join(format!("jwt_test_{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    for (kid, seed, private) in keys {
+      write_test_key(&dir, kid, *seed, *private);
+    }
+    let keys = load_jwt_keys(&dir, active_kid).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    keys
+  }
+
+  fn token(keys: &JwtKeys, user_uuid: &Uuid) -> String {
+    create_jwt_token(user_uuid, &Uuid::new_v4(), 0, &[], keys, 900).unwrap_or_else(|_| panic!("signing failed"))
+  }
+
+  fn kid(token: &str) -> String {
+    decode_header(token).unwrap().kid.unwrap()
+  }
+
+  #[test]
+  fn new_tokens_use_the_active_kid() {
+    let keys = load_keys(&[("2025-01", 1, false), ("2025-02", 2, true)], "2025-02");
+    let user_uuid = Uuid::new_v4();
+    let token = token(&keys, &user_uuid);
+    assert_eq!(kid(&token), "2025-02");
+    let Ok(claims) = get_jwt_claims(&token, &keys) else { panic!("token should verify") };
+    assert_eq!(claims.sub, user_uuid.to_string());
+  }
+
+  #[test]
+  fn tokens_of_a_retired_kid_still_verify() {
+    let before = load_keys(&[("2025-01", 1, true)], "2025-01");
+    let user_uuid = Uuid::new_v4();
+    let old_token = token(&before, &user_uuid);
+
+    // Rotated: 2025-02 signs, only the public half of 2025-01 is kept
+    let after = load_keys(&[("2025-01", 1, false), ("2025-02", 2, true)], "2025-02");
+    let Ok(claims) = get_jwt_claims(&old_token, &after) else { panic!("token of the retired kid should verify") };
+    assert_eq!(claims.sub, user_uuid.to_string());
+    assert_eq!(kid(&token(&after, &user_uuid)), "2025-02");
+  }
+
+  #[test]
+  fn unknown_kids_are_rejected() {
+    let keys = load_keys(&[("2025-02", 2, true)], "2025-02");
+    // Signed by a kid that was deleted after rotating, or never ours
+    let other = load_keys(&[("2025-01", 1, true)], "2025-01");
+    assert!(matches!(get_jwt_claims(&token(&other, &Uuid::new_v4()), &keys), Err(JWTError::InvalidToken)));
+  }
+
+  #[test]
+  fn a_known_kid_with_the_wrong_key_is_rejected() {
+    let keys = load_keys(&[("2025-02", 2, true)], "2025-02");
+    let forged = load_keys(&[("2025-02", 9, true)], "2025-02");
+    assert!(matches!(get_jwt_claims(&token(&forged, &Uuid::new_v4()), &keys), Err(JWTError::DecodingError)));
+  }
+}
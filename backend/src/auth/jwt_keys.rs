@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use axum::{
+  extract::{Json, State},
+  http::header,
+  response::IntoResponse,
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use jsonwebtoken::jwk::{
+  AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+  OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use rsa::traits::PublicKeyParts;
+
+use crate::app_state::AppState;
+
+// Keys live in a directory as `<kid>.pem`, either a PKCS#8 private key or an SPKI public key,
+// Ed25519 (EdDSA) or RSA (RS256). Generate them with:
+//   openssl genpkey -algorithm ed25519 -out <kid>.pem
+//   openssl genpkey -algorithm rsa -pkeyopt rsa_keygen_bits:2048 -out <kid>.pem
+// Tokens are signed with the active kid only. To rotate, add a new private key, make it active,
+// and replace the old private key with its public key (`openssl pkey -in old.pem -pubout`) so
+// its tokens keep validating. Delete it once they have expired.
+
+pub struct SigningKey {
+  pub kid: String,
+  pub algorithm: Algorithm,
+  pub encoding_key: EncodingKey,
+}
+
+pub struct VerifyingKey {
+  pub algorithm: Algorithm,
+  pub decoding_key: DecodingKey,
+  pub jwk: Jwk,
+}
+
+pub struct JwtKeys {
+  pub signing_key: SigningKey,
+  pub verifying_keys: HashMap<String, VerifyingKey>,
+}
+
+struct LoadedKey {
+  algorithm: Algorithm,
+  encoding_key: Option<EncodingKey>,
+  jwk: Jwk,
+}
+
+fn build_jwk(kid: &str, algorithm: Algorithm, parameters: AlgorithmParameters) -> Jwk {
+  Jwk {
+    common: CommonParameters {
+      public_key_use: Some(PublicKeyUse::Signature),
+      key_algorithm: Some(match algorithm {
+        Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+        _ => KeyAlgorithm::RS256,
+      }),
+      key_id: Some(kid.to_string()),
+      ..Default::default()
+    },
+    algorithm: parameters,
+  }
+}
+
+fn ed25519_jwk(kid: &str, public_key: &ed25519_dalek::VerifyingKey) -> Jwk {
+  build_jwk(kid, Algorithm::EdDSA, AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+    key_type: OctetKeyPairType::OctetKeyPair,
+    curve: EllipticCurve::Ed25519,
+    x: URL_SAFE_NO_PAD.encode(public_key.as_bytes()),
+  }))
+}
+
+fn rsa_jwk(kid: &str, public_key: &rsa::RsaPublicKey) -> Jwk {
+  build_jwk(kid, Algorithm::RS256, AlgorithmParameters::RSA(RSAKeyParameters {
+    key_type: RSAKeyType::RSA,
+    n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+    e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+  }))
+}
+
+fn load_key(kid: &str, pem: &str) -> Result<LoadedKey, anyhow::Error> {
+  if let Ok(private_key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+    return Ok(LoadedKey {
+      algorithm: Algorithm::EdDSA,
+      encoding_key: Some(EncodingKey::from_ed_pem(pem.as_bytes())?),
+      jwk: ed25519_jwk(kid, &private_key.verifying_key()),
+    });
+  }
+  if let Ok(public_key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+    return Ok(LoadedKey {
+      algorithm: Algorithm::EdDSA,
+      encoding_key: None,
+      jwk: ed25519_jwk(kid, &public_key),
+    });
+  }
+  if let Ok(private_key) = rsa::RsaPrivateKey::from_pkcs8_pem(pem) {
+    return Ok(LoadedKey {
+      algorithm: Algorithm::RS256,
+      encoding_key: Some(EncodingKey::from_rsa_pem(pem.as_bytes())?),
+      jwk: rsa_jwk(kid, &private_key.to_public_key()),
+    });
+  }
+  if let Ok(public_key) = rsa::RsaPublicKey::from_public_key_pem(pem) {
+    return Ok(LoadedKey {
+      algorithm: Algorithm::RS256,
+      encoding_key: None,
+      jwk: rsa_jwk(kid, &public_key),
+    });
+  }
+  Err(anyhow::anyhow!("{kid}.pem is not an Ed25519 or RSA key in PKCS#8/SPKI PEM format"))
+}
+
+pub fn load_jwt_keys(dir: &Path, active_kid: &str) -> Result<JwtKeys, anyhow::Error> {
+  let mut signing_key = None;
+  let mut verifying_keys = HashMap::new();
+
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.extension().and_then(|extension| extension.to_str()) != Some("pem") {
+      continue;
+    }
+    let Some(kid) = path.file_stem().and_then(|stem| stem.to_str()) else {
+      continue;
+    };
+
+    let key = load_key(kid, &fs::read_to_string(&path)?)?;
+    if kid == active_kid {
+      let encoding_key = key.encoding_key
+        .ok_or_else(|| anyhow::anyhow!("active key {kid}.pem must be a private key"))?;
+      signing_key = Some(SigningKey {
+        kid: kid.to_string(),
+        algorithm: key.algorithm,
+        encoding_key,
+      });
+    }
+    verifying_keys.insert(kid.to_string(), VerifyingKey {
+      algorithm: key.algorithm,
+      decoding_key: DecodingKey::from_jwk(&key.jwk)?,
+      jwk: key.jwk,
+    });
+  }
+
+  let signing_key = signing_key
+    .ok_or_else(|| anyhow::anyhow!("active key {active_kid}.pem not found in {}", dir.display()))?;
+  Ok(JwtKeys { signing_key, verifying_keys })
+}
+
+// Writes `<kid>.pem` for an Ed25519 key made from `seed`, the private key or, for a retired kid, its public half
+#[cfg(test)]
+pub fn write_test_key(dir: &Path, kid: &str, seed: u8, private: bool) {
+  use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey, spki::der::pem::LineEnding};
+
+  let key = ed25519_dalek::SigningKey::from_bytes(&[seed; 32]);
+  let pem = match private {
+    true => key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string(),
+    false => key.verifying_key().to_public_key_pem(LineEnding::LF).unwrap(),
+  };
+  fs::write(dir.join(format!("{kid}.pem")), pem).unwrap();
+}
+
+/*** Handlers ***/
+
+// Lets other services verify our tokens without sharing any secret
+pub async fn handle_jwks(State(app_state): State<AppState>) -> impl IntoResponse {
+  let jwks = JwkSet {
+    keys: app_state.jwt_keys.verifying_keys.values().map(|key| key.jwk.clone()).collect(),
+  };
+  ([(header::CACHE_CONTROL, "public, max-age=300")], Json(jwks))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+
+  fn key_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("jwt_keys_test_{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn loads_the_active_and_retired_keys() {
+    let dir = key_dir();
+    write_test_key(&dir, "2025-01", 1, false);
+    write_test_key(&dir, "2025-02", 2, true);
+    fs::write(dir.join("README.txt"), "not a key").unwrap();
+
+    let keys = load_jwt_keys(&dir, "2025-02").unwrap();
+    assert_eq!(keys.signing_key.kid, "2025-02");
+    assert_eq!(keys.signing_key.algorithm, Algorithm::EdDSA);
+    let mut kids: Vec<&str> = keys.verifying_keys.keys().map(String::as_str).collect();
+    kids.sort();
+    assert_eq!(kids, ["2025-01", "2025-02"]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn active_key_must_be_a_loaded_private_key() {
+    let dir = key_dir();
+    write_test_key(&dir, "2025-01", 1, false);
+    assert!(load_jwt_keys(&dir, "2025-01").is_err());
+    assert!(load_jwt_keys(&dir, "2025-02").is_err());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
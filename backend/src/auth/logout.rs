@@ -0,0 +1,72 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthClaims;
+use crate::auth::sessions;
+use crate::auth::db::jwt_denylist;
+
+/*** Json Structs **/
+
+#[derive(Serialize)]
+pub struct LogoutResponse {
+  error_code: Option<LogoutError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogoutError {
+  InternalError,
+}
+
+/*** Helpers ***/
+
+fn success_response(event: &str, user_uuid: &Uuid) -> (StatusCode, Json<LogoutResponse>) {
+  tracing::info!(
+    event = event,
+    user_uuid = %user_uuid,
+  );
+  (StatusCode::OK, Json(LogoutResponse {
+    error_code: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<LogoutResponse>) {
+  tracing::error!(
+    event = "logout_failure",
+    error_code = ?(LogoutError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(LogoutResponse {
+    error_code: Some(LogoutError::InternalError),
+  }))
+}
+
+/*** Handlers ***/
+
+// Signs out the current device: denies this access token and kills its refresh token family
+pub async fn handle_logout(State(app_state): State<AppState>, AuthClaims { user_uuid: uuid, claims }: AuthClaims) -> (StatusCode, Json<LogoutResponse>) {
+  let remaining_sec = claims.exp - OffsetDateTime::now_utc().unix_timestamp();
+  if jwt_denylist::deny_token(&app_state.redis_pool, &claims.jti, remaining_sec.max(0) as u64).await.is_err() {
+    return internal_error_response("cannot_connect_to_redis");
+  }
+  if sessions::revoke_session(&app_state, &uuid, &claims.sid).await.is_err() {
+    return internal_error_response("session_revoke_failed");
+  }
+
+  success_response("logout_success", &uuid)
+}
+
+// Signs out every device by bumping the user's token version
+pub async fn handle_logout_all(State(app_state): State<AppState>, AuthClaims { user_uuid: uuid, .. }: AuthClaims) -> (StatusCode, Json<LogoutResponse>) {
+  if sessions::revoke_all_sessions(&app_state, &uuid).await.is_err() {
+    return internal_error_response("session_revoke_failed");
+  }
+
+  success_response("logout_all_success", &uuid)
+}
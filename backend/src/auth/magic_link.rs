@@ -0,0 +1,132 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::request_id;
+use crate::api::send_email::{send_email, escape_html};
+use crate::auth::email_link;
+use crate::auth::hashing::{generate_token, hash_token};
+use crate::auth::db::email_link as email_link_db;
+use crate::auth::db::magic_link::{self, MagicLink};
+use crate::auth::db::user_data::{self, UserData};
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest {
+  email: String,
+  #[serde(default)]
+  bind_to_device: bool,
+}
+
+#[derive(Serialize)]
+pub struct MagicLinkResponse {
+  confirmation_code: Option<String>, // Kept by the requesting browser, or typed in when the link is opened elsewhere
+}
+
+const MAGIC_LINK: &str = "magic_link";
+const MAGIC_LINK_PAGE: &str = "magic-link.html";
+
+/*** Helpers ***/
+
+fn generate_confirmation_code() -> String {
+  let code: u32 = rand::rng().random_range(0..1_000_000);
+  format!("{:06}", code)
+}
+
+async fn send_magic_link_email(app_state: &AppState, user: &UserData, confirmation_code: Option<String>) -> Result<(), anyhow::Error> {
+  let token = generate_token();
+  let link = MagicLink {
+    uuid: user.uuid,
+    email: user.email.clone(),
+    confirmation_code,
+  };
+  magic_link::store_link(&app_state.redis_pool, &hash_token(&token), &link, app_state.magic_link_expiration_sec).await?;
+
+  let url = email_link::link_url(&app_state.app_base_url, MAGIC_LINK_PAGE, &token);
+  let html_body = format!(
+    "<p>Hi {},</p>\
+     <p>Click the link below to sign in to Getly:</p>\
+     <p><a href=\"{url}\">Sign me in</a></p>\
+     <p>The link will last for {} minutes and can be used once. If you didn't ask for this, you can ignore this email.</p>",
+    escape_html(&user.name),
+    app_state.magic_link_expiration_sec / 60,
+  );
+
+  send_email(&app_state.resend, &app_state.email_from, vec![&user.email], "Your Getly sign in link", &html_body).await?;
+  Ok(())
+}
+
+// Sent in the background, so unknown emails answer just as fast as registered ones
+fn send_magic_link_in_background(app_state: &AppState, user: UserData, confirmation_code: Option<String>) {
+  let app_state = app_state.clone();
+  request_id::spawn(async move {
+    if send_magic_link_email(&app_state, &user, confirmation_code).await.is_err() {
+      tracing::error!(
+        event = "magic_link_request_failure",
+        user_uuid = %user.uuid,
+        reason = "send_email_failed",
+      );
+    }
+  });
+}
+
+// Exchanges a link for its user, returns None on an unknown, used or mismatched link
+pub async fn consume_magic_link(app_state: &AppState, token: &str, confirmation_code: Option<&str>) -> Result<Option<UserData>, anyhow::Error> {
+  let Some(link) = magic_link::take_link(&app_state.redis_pool, &hash_token(token)).await? else {
+    return Ok(None);
+  };
+  if let Some(expected) = &link.confirmation_code && confirmation_code != Some(expected.as_str()) {
+    return Ok(None);
+  }
+  let Some(user) = user_data::get_user_by_uuid(&app_state.pool, &link.uuid).await? else {
+    return Ok(None);
+  };
+  // The email changed since the link was sent
+  if user.email != link.email {
+    return Ok(None);
+  }
+  Ok(Some(user))
+}
+
+fn success_response(event: &str, confirmation_code: Option<String>) -> (StatusCode, Json<MagicLinkResponse>) {
+  tracing::info!(event = event);
+  (StatusCode::OK, Json(MagicLinkResponse {
+    confirmation_code,
+  }))
+}
+
+/*** Handlers ***/
+
+// Always answers OK, so it can't be used to find out which emails have an account
+pub async fn handle_send_link(State(app_state): State<AppState>, Json(payload): Json<MagicLinkRequest>) -> (StatusCode, Json<MagicLinkResponse>) {
+  let confirmation_code = payload.bind_to_device.then(generate_confirmation_code);
+
+  match email_link_db::try_start_resend_cooldown(&app_state.redis_pool, MAGIC_LINK, &payload.email, app_state.magic_link_resend_sec).await {
+    Ok(true) => {},
+    Ok(false) => return success_response("magic_link_request_throttled", confirmation_code),
+    Err(_) => {
+      tracing::error!(
+        event = "magic_link_request_failure",
+        reason = "cannot_connect_to_redis",
+      );
+      return success_response("magic_link_request", confirmation_code);
+    },
+  }
+
+  match user_data::get_user_by_email(&app_state.pool, &payload.email).await {
+    Ok(Some(user)) => send_magic_link_in_background(&app_state, user, confirmation_code.clone()),
+    Ok(None) => {},
+    Err(_) => {
+      tracing::error!(
+        event = "magic_link_request_failure",
+        reason = "db_error",
+      );
+    },
+  }
+  success_response("magic_link_request", confirmation_code)
+}
@@ -0,0 +1,333 @@
+use axum::{
+  extract::{Json, Path, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+use webauthn_rs::prelude::{CreationChallengeResponse, Passkey, PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse};
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthUser;
+use crate::auth::sessions;
+use crate::auth::security_notification::send_security_notification;
+use crate::auth::webauthn;
+use crate::auth::db::webauthn_credential::{self, WebauthnCredential};
+use crate::auth::db::webauthn_challenge;
+use crate::auth::db::user_data::{self, UserData};
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct FinishRegistrationRequest {
+  name: String,
+  credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Deserialize)]
+pub struct RenamePasskeyRequest {
+  name: String,
+}
+
+#[derive(Serialize)]
+pub struct PasskeyInfo {
+  credential_id: String,
+  name: String,
+  created_at: OffsetDateTime,
+  last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct PasskeysResponse {
+  error_code: Option<PasskeysError>,
+  passkeys: Option<Vec<PasskeyInfo>>,
+  registration_options: Option<CreationChallengeResponse>,
+}
+
+#[derive(Serialize)]
+pub struct PasskeySignInStartResponse {
+  error_code: Option<PasskeysError>,
+  challenge_id: Option<Uuid>,
+  options: Option<RequestChallengeResponse>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasskeysError {
+  InvalidName,
+  RegistrationNotStarted,
+  RegistrationFailed,
+  PasskeyNotFound,
+  NeedToReauthenticate,
+  InternalError,
+}
+
+const NAME_MAX_LENGTH: usize = 50;
+
+/*** Helpers ***/
+
+fn normalize_name(name: &str) -> Option<String> {
+  let name = name.trim();
+  let length = name.chars().count();
+  (1..=NAME_MAX_LENGTH).contains(&length).then(|| name.to_string())
+}
+
+fn passkey_info(credential: WebauthnCredential) -> PasskeyInfo {
+  PasskeyInfo {
+    credential_id: credential.credential_id,
+    name: credential.name,
+    created_at: credential.created_at,
+    last_used_at: credential.last_used_at,
+  }
+}
+
+fn parse_passkeys(credentials: &[WebauthnCredential]) -> Result<Vec<Passkey>, serde_json::Error> {
+  credentials.iter().map(|credential| serde_json::from_str(&credential.passkey)).collect()
+}
+
+async fn notify(app_state: &AppState, user: &UserData, subject: &str, message: &str) {
+  if send_security_notification(app_state, user, subject, message).await.is_err() {
+    tracing::error!(
+      event = "passkeys_notification_failure",
+      user_uuid = %user.uuid,
+      reason = "send_email_failed",
+    );
+  }
+}
+
+// Checks a sign in assertion, returns the user it belongs to or None if it doesn't verify
+pub async fn verify_assertion(app_state: &AppState, challenge_id: &Uuid, credential: &PublicKeyCredential) -> Result<Option<UserData>, anyhow::Error> {
+  let Some(state) = webauthn_challenge::take_authentication(&app_state.redis_pool, challenge_id).await? else {
+    return Ok(None);
+  };
+  let Ok((user_uuid, credential_id)) = webauthn::identify_credential(&app_state.webauthn, credential) else {
+    return Ok(None);
+  };
+  let Some(stored) = webauthn_credential::get_credential(&app_state.pool, &user_uuid, &credential_id).await? else {
+    return Ok(None);
+  };
+  let mut passkey: Passkey = serde_json::from_str(&stored.passkey)?;
+
+  if webauthn::finish_authentication(&app_state.webauthn, credential, state, &mut passkey).is_err() {
+    return Ok(None);
+  }
+  webauthn_credential::record_use(&app_state.pool, &credential_id, &serde_json::to_string(&passkey)?).await?;
+
+  Ok(user_data::get_user_by_uuid(&app_state.pool, &user_uuid).await?)
+}
+
+fn empty_response(error_code: Option<PasskeysError>) -> PasskeysResponse {
+  PasskeysResponse {
+    error_code,
+    passkeys: None,
+    registration_options: None,
+  }
+}
+
+fn success_response(event: &str, user: &UserData) -> (StatusCode, Json<PasskeysResponse>) {
+  tracing::info!(
+    event = event,
+    user_uuid = %user.uuid,
+  );
+  (StatusCode::OK, Json(empty_response(None)))
+}
+
+fn warn_response(status_code: StatusCode, error_code: PasskeysError) -> (StatusCode, Json<PasskeysResponse>) {
+  tracing::warn!(
+    event = "passkeys_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(empty_response(Some(error_code))))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<PasskeysResponse>) {
+  tracing::error!(
+    event = "passkeys_failure",
+    error_code = ?(PasskeysError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(empty_response(Some(PasskeysError::InternalError))))
+}
+
+/*** Handlers ***/
+
+pub async fn handle_register_start(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser) -> (StatusCode, Json<PasskeysResponse>) {
+  // A passkey signs in without TOTP, so adding one takes a fresh sign in and not just an access token
+  match sessions::is_recently_authenticated(&app_state, &claims).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::FORBIDDEN, PasskeysError::NeedToReauthenticate),
+    Err(_) => return internal_error_response("db_error"),
+  }
+  let Ok(credentials) = webauthn_credential::get_credentials(&app_state.pool, &user.uuid).await else {
+    return internal_error_response("db_error");
+  };
+  let Ok(existing) = parse_passkeys(&credentials) else {
+    return internal_error_response("passkey_parse_failed");
+  };
+  let Ok((options, state)) = webauthn::start_registration(&app_state.webauthn, &user, &existing) else {
+    return internal_error_response("webauthn_start_failed");
+  };
+  if webauthn_challenge::store_registration(&app_state.redis_pool, &user.uuid, &state, app_state.webauthn_challenge_expiration_sec).await.is_err() {
+    return internal_error_response("cannot_connect_to_redis");
+  }
+
+  (StatusCode::OK, Json(PasskeysResponse {
+    error_code: None,
+    passkeys: None,
+    registration_options: Some(options),
+  }))
+}
+
+pub async fn handle_register_finish(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser, Json(payload): Json<FinishRegistrationRequest>) -> (StatusCode, Json<PasskeysResponse>) {
+  // Checked again, the sign in may have aged out since the registration started
+  match sessions::is_recently_authenticated(&app_state, &claims).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::FORBIDDEN, PasskeysError::NeedToReauthenticate),
+    Err(_) => return internal_error_response("db_error"),
+  }
+  let Some(name) = normalize_name(&payload.name) else {
+    return warn_response(StatusCode::BAD_REQUEST, PasskeysError::InvalidName);
+  };
+  let state = match webauthn_challenge::take_registration(&app_state.redis_pool, &user.uuid).await {
+    Ok(Some(state)) => state,
+    Ok(None) => return warn_response(StatusCode::BAD_REQUEST, PasskeysError::RegistrationNotStarted),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  };
+  let Ok(passkey) = webauthn::finish_registration(&app_state.webauthn, &payload.credential, &state) else {
+    return warn_response(StatusCode::BAD_REQUEST, PasskeysError::RegistrationFailed);
+  };
+
+  let Ok(serialized) = serde_json::to_string(&passkey) else {
+    return internal_error_response("passkey_serialize_failed");
+  };
+  match webauthn_credential::create_credential(&app_state.pool, &webauthn::credential_id_string(&passkey), &user.uuid, &name, &serialized).await {
+    Ok(()) => {},
+    // The authenticator was already registered, excluded credentials are only a hint to the browser
+    Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+      return warn_response(StatusCode::CONFLICT, PasskeysError::RegistrationFailed);
+    },
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  notify(&app_state, &user, "A passkey was added to your Getly account", &format!("The passkey \"{name}\" was just added to your Getly account.")).await;
+  success_response("passkey_register_success", &user)
+}
+
+pub async fn handle_list_passkeys(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser) -> (StatusCode, Json<PasskeysResponse>) {
+  match webauthn_credential::get_credentials(&app_state.pool, &user.uuid).await {
+    Ok(credentials) => (StatusCode::OK, Json(PasskeysResponse {
+      error_code: None,
+      passkeys: Some(credentials.into_iter().map(passkey_info).collect()),
+      registration_options: None,
+    })),
+    Err(_) => internal_error_response("db_error"),
+  }
+}
+
+pub async fn handle_rename_passkey(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser, Path(credential_id): Path<String>, Json(payload): Json<RenamePasskeyRequest>) -> (StatusCode, Json<PasskeysResponse>) {
+  let Some(name) = normalize_name(&payload.name) else {
+    return warn_response(StatusCode::BAD_REQUEST, PasskeysError::InvalidName);
+  };
+  match webauthn_credential::rename_credential(&app_state.pool, &user.uuid, &credential_id, &name).await {
+    Ok(true) => success_response("passkey_rename_success", &user),
+    Ok(false) => warn_response(StatusCode::NOT_FOUND, PasskeysError::PasskeyNotFound),
+    Err(_) => internal_error_response("db_error"),
+  }
+}
+
+pub async fn handle_delete_passkey(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser, Path(credential_id): Path<String>) -> (StatusCode, Json<PasskeysResponse>) {
+  match webauthn_credential::delete_credential(&app_state.pool, &user.uuid, &credential_id).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::NOT_FOUND, PasskeysError::PasskeyNotFound),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  notify(&app_state, &user, "A passkey was removed from your Getly account", "A passkey was just removed from your Getly account.").await;
+  success_response("passkey_delete_success", &user)
+}
+
+// First half of a passkey sign in, the answer goes to /auth/sign-in with method webauthn
+pub async fn handle_sign_in_start(State(app_state): State<AppState>) -> (StatusCode, Json<PasskeySignInStartResponse>) {
+  let challenge_id = Uuid::new_v4();
+  let stored = match webauthn::start_authentication(&app_state.webauthn) {
+    Ok((options, state)) => webauthn_challenge::store_authentication(&app_state.redis_pool, &challenge_id, &state, app_state.webauthn_challenge_expiration_sec).await
+      .map(|_| options),
+    Err(error) => Err(error.into()),
+  };
+
+  match stored {
+    Ok(options) => (StatusCode::OK, Json(PasskeySignInStartResponse {
+      error_code: None,
+      challenge_id: Some(challenge_id),
+      options: Some(options),
+    })),
+    Err(_) => {
+      tracing::error!(
+        event = "passkey_sign_in_start_failure",
+        error_code = ?(PasskeysError::InternalError),
+      );
+      (StatusCode::INTERNAL_SERVER_ERROR, Json(PasskeySignInStartResponse {
+        error_code: Some(PasskeysError::InternalError),
+        challenge_id: None,
+        options: None,
+      }))
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use webauthn_authenticator_rs::WebauthnAuthenticator;
+  use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+  use webauthn_rs::prelude::Url;
+  use crate::app_state::test_app_state;
+  use crate::auth::jwt::JWTClaims;
+
+  // A user whose only session signed in an hour ago, past reauth_max_age_sec. Returns the user and session ids.
+  async fn stale_sign_in(app_state: &AppState) -> (Uuid, Uuid) {
+    let uuid = Uuid::new_v4();
+    let sid = Uuid::new_v4();
+    let phone_num = format!("+1555{:07}", rand::random_range(0..10_000_000));
+    sqlx::query("INSERT INTO users (uuid, email, name, phone_num) VALUES ($1, $2, 'Dana Cohen', $3)")
+      .bind(uuid).bind(format!("{uuid}@example.com")).bind(&phone_num)
+      .execute(&app_state.pool).await.unwrap();
+    sqlx::query("INSERT INTO sessions (id, user_uuid, method, created_at, expires_at) VALUES ($1, $2, 'password', now() - interval '1 hour', now() + interval '1 day')")
+      .bind(sid).bind(uuid)
+      .execute(&app_state.pool).await.unwrap();
+    (uuid, sid)
+  }
+
+  // What the extractor hands the handler for a valid access token of that session
+  async fn auth_user(app_state: &AppState, uuid: &Uuid, sid: &Uuid) -> AuthUser {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    AuthUser {
+      user: user_data::get_user_by_uuid(&app_state.pool, uuid).await.unwrap().unwrap(),
+      claims: JWTClaims { sub: uuid.to_string(), exp: now + 900, iat: now, ver: 0, sid: *sid, jti: Uuid::new_v4(), roles: Vec::new() },
+    }
+  }
+
+  #[tokio::test]
+  async fn registration_needs_a_recent_sign_in() {
+    let app_state = test_app_state();
+    let (uuid, sid) = stale_sign_in(&app_state).await;
+
+    let (status, Json(response)) = handle_register_start(State(app_state.clone()), auth_user(&app_state, &uuid, &sid).await).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(matches!(response.error_code, Some(PasskeysError::NeedToReauthenticate)));
+    assert!(response.registration_options.is_none());
+
+    // A credential made for options obtained some other way is refused the same way
+    let user = user_data::get_user_by_uuid(&app_state.pool, &uuid).await.unwrap().unwrap();
+    let (options, _) = webauthn::start_registration(&app_state.webauthn, &user, &[]).unwrap();
+    let credential = WebauthnAuthenticator::new(SoftPasskey::new(true))
+      .do_registration(Url::parse(&app_state.app_base_url).unwrap(), options).unwrap();
+    let payload = FinishRegistrationRequest { name: "Laptop".to_string(), credential };
+    let (status, Json(response)) = handle_register_finish(State(app_state.clone()), auth_user(&app_state, &uuid, &sid).await, Json(payload)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(matches!(response.error_code, Some(PasskeysError::NeedToReauthenticate)));
+    assert!(webauthn_credential::get_credentials(&app_state.pool, &uuid).await.unwrap().is_empty());
+
+    sqlx::query("DELETE FROM users WHERE uuid = $1").bind(uuid).execute(&app_state.pool).await.unwrap();
+  }
+}
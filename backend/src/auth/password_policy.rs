@@ -0,0 +1,176 @@
+use std::fs;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+// Argon2 is slow on purpose, so cap the input to keep hashing cheap enough
+const MAX_LENGTH: usize = 128;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordPolicyError {
+  TooShort,
+  TooLong,
+  ContainsEmail,
+  Breached,
+  TooWeak,
+}
+
+pub struct PasswordPolicy {
+  pub min_length: usize,
+  pub min_strength: u8, // zxcvbn score, 0 (guessable in 10^3 tries) to 4 (more than 10^10)
+  breached_hashes: Vec<[u8; 20]>,
+}
+
+impl PasswordPolicy {
+  // The breached list has one SHA-1 per line in hex, sorted, with an optional `:count` suffix
+  // like the Have I Been Pwned downloads. Without a file only the length and strength checks run.
+  pub fn new(min_length: usize, min_strength: u8, breached_list_path: Option<&str>) -> Result<PasswordPolicy, anyhow::Error> {
+    let mut breached_hashes = Vec::new();
+    if let Some(path) = breached_list_path {
+      for line in fs::read_to_string(path)?.lines() {
+        let hash = line.split(':').next().unwrap_or_default().trim();
+        if hash.is_empty() {
+          continue;
+        }
+        let mut bytes = [0u8; 20];
+        hex::decode_to_slice(hash, &mut bytes)?;
+        breached_hashes.push(bytes);
+      }
+      // Binary search below relies on the order, a hand edited file might not keep it
+      if !breached_hashes.is_sorted() {
+        breached_hashes.sort_unstable();
+      }
+    }
+    tracing::info!(
+      event = "password_policy_loaded",
+      breached_hashes = breached_hashes.len(),
+    );
+
+    Ok(PasswordPolicy {
+      min_length,
+      min_strength,
+      breached_hashes,
+    })
+  }
+
+  fn is_breached(&self, password: &str) -> bool {
+    let hash: [u8; 20] = Sha1::digest(password.as_bytes()).into();
+    self.breached_hashes.binary_search(&hash).is_ok()
+  }
+
+  // `user_inputs` are the user's own details (email, name...), passwords built from them score lower
+  pub fn check(&self, password: &str, email: &str, user_inputs: &[&str]) -> Result<(), PasswordPolicyError> {
+    let length = password.chars().count();
+    if length < self.min_length {
+      return Err(PasswordPolicyError::TooShort);
+    }
+    if length > MAX_LENGTH {
+      return Err(PasswordPolicyError::TooLong);
+    }
+    let local_part = email.split('@').next().unwrap_or_default().to_lowercase();
+    if !local_part.is_empty() && password.to_lowercase().contains(&local_part) {
+      return Err(PasswordPolicyError::ContainsEmail);
+    }
+    if self.is_breached(password) {
+      return Err(PasswordPolicyError::Breached);
+    }
+    let mut inputs = vec![email];
+    inputs.extend_from_slice(user_inputs);
+    if u8::from(zxcvbn::zxcvbn(password, &inputs).score()) < self.min_strength {
+      return Err(PasswordPolicyError::TooWeak);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+
+  const EMAIL: &str = "dana@example.com";
+  const STRONG_PASSWORD: &str = "correct-horse-battery-staple-42";
+
+  fn sha1_hex(password: &str) -> String {
+    hex::encode_upper(Sha1::digest(password.as_bytes()))
+  }
+
+  fn write_list(name: &str, lines: &[String]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("password_policy_{}_{}.txt", std::process::id(), name));
+    fs::write(&path, lines.join("\n")).unwrap();
+    path
+  }
+
+  fn policy_with_list(name: &str, lines: &[String]) -> PasswordPolicy {
+    let path = write_list(name, lines);
+    let policy = PasswordPolicy::new(8, 3, path.to_str()).unwrap();
+    fs::remove_file(path).unwrap();
+    policy
+  }
+
+  #[test]
+  fn strong_password_passes() {
+    let policy = PasswordPolicy::new(8, 3, None).unwrap();
+    assert!(policy.check(STRONG_PASSWORD, EMAIL, &["Dana Cohen"]).is_ok());
+  }
+
+  #[test]
+  fn too_short() {
+    let policy = PasswordPolicy::new(8, 0, None).unwrap();
+    assert!(matches!(policy.check("x7#kQ", EMAIL, &[]), Err(PasswordPolicyError::TooShort)));
+  }
+
+  #[test]
+  fn too_long() {
+    let policy = PasswordPolicy::new(8, 0, None).unwrap();
+    assert!(policy.check(&"a".repeat(MAX_LENGTH), EMAIL, &[]).is_ok());
+    assert!(matches!(policy.check(&"a".repeat(MAX_LENGTH + 1), EMAIL, &[]), Err(PasswordPolicyError::TooLong)));
+  }
+
+  #[test]
+  fn contains_email() {
+    let policy = PasswordPolicy::new(8, 0, None).unwrap();
+    assert!(matches!(policy.check("xX-DANA-horse-91", EMAIL, &[]), Err(PasswordPolicyError::ContainsEmail)));
+  }
+
+  #[test]
+  fn too_weak() {
+    let policy = PasswordPolicy::new(8, 3, None).unwrap();
+    assert!(matches!(policy.check("abcdefgh", EMAIL, &[]), Err(PasswordPolicyError::TooWeak)));
+    assert!(matches!(policy.check("password1", EMAIL, &[]), Err(PasswordPolicyError::TooWeak)));
+  }
+
+  #[test]
+  fn breached() {
+    let policy = policy_with_list("breached", &[sha1_hex(STRONG_PASSWORD)]);
+    assert!(matches!(policy.check(STRONG_PASSWORD, EMAIL, &[]), Err(PasswordPolicyError::Breached)));
+    assert!(policy.check("another-long-horse-battery-77", EMAIL, &[]).is_ok());
+  }
+
+  #[test]
+  fn count_suffix_and_blank_lines_are_ignored() {
+    let lines = vec![format!("{}:3861493", sha1_hex(STRONG_PASSWORD)), String::new(), format!("{}:12", sha1_hex("another-long-horse-battery-77"))];
+    let policy = policy_with_list("count_suffix", &lines);
+    assert_eq!(policy.breached_hashes.len(), 2);
+    assert!(matches!(policy.check(STRONG_PASSWORD, EMAIL, &[]), Err(PasswordPolicyError::Breached)));
+  }
+
+  #[test]
+  fn unsorted_list_is_sorted() {
+    let mut lines: Vec<String> = (0..50).map(|i| sha1_hex(&format!("breached-horse-battery-{i}"))).collect();
+    lines.sort_unstable_by(|a, b| b.cmp(a));
+    let policy = policy_with_list("unsorted", &lines);
+    assert!(policy.breached_hashes.is_sorted());
+    for i in 0..50 {
+      let password = format!("breached-horse-battery-{i}");
+      assert!(matches!(policy.check(&password, EMAIL, &[]), Err(PasswordPolicyError::Breached)), "{password}");
+    }
+  }
+
+  #[test]
+  fn malformed_list_fails_to_load() {
+    let path = write_list("malformed", &["not-a-sha1".to_string()]);
+    assert!(PasswordPolicy::new(8, 3, path.to_str()).is_err());
+    fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,189 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::request_id;
+use crate::api::send_email::{send_email, escape_html};
+use crate::auth::email_link;
+use crate::auth::hashing::{hash_password, generate_token, hash_token};
+use crate::auth::sessions;
+use crate::auth::password_policy::PasswordPolicyError;
+use crate::auth::db::email_link as email_link_db;
+use crate::auth::db::user_data::{self, UserData};
+use crate::auth::db::password_reset;
+use crate::auth::db::sign_in_attempts;
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct ForgotRequest {
+  email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetRequest {
+  token: String,
+  password: String,
+}
+
+#[derive(Serialize)]
+pub struct PasswordResetResponse {
+  error_code: Option<PasswordResetError>,
+  policy_error: Option<PasswordPolicyError>, // Set with weak_password
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordResetError {
+  InvalidToken,
+  WeakPassword,
+  InternalError,
+}
+
+const PASSWORD_RESET: &str = "password_reset";
+const RESET_PASSWORD_PAGE: &str = "reset-password.html";
+
+/*** Helpers ***/
+
+async fn send_reset_email(app_state: &AppState, user: &UserData) -> Result<(), anyhow::Error> {
+  let token = generate_token();
+  password_reset::store_reset_token(&app_state.redis_pool, &hash_token(&token), &user.uuid, app_state.password_reset_expiration_sec).await?;
+
+  let url = email_link::link_url(&app_state.app_base_url, RESET_PASSWORD_PAGE, &token);
+  let html_body = format!(
+    "<p>Hi {},</p>\
+     <p>We received a request to reset your Getly password. Click the link below to choose a new one:</p>\
+     <p><a href=\"{url}\">Reset my password</a></p>\
+     <p>The link will last for {} minutes. If you didn't ask for this, you can ignore this email.</p>",
+    escape_html(&user.name),
+    app_state.password_reset_expiration_sec / 60,
+  );
+
+  send_email(&app_state.resend, &app_state.email_from, vec![&user.email], "Reset your Getly password", &html_body).await?;
+  Ok(())
+}
+
+// Sent in the background, so unknown emails answer just as fast as registered ones
+fn send_reset_email_in_background(app_state: &AppState, user: UserData) {
+  let app_state = app_state.clone();
+  request_id::spawn(async move {
+    if send_reset_email(&app_state, &user).await.is_err() {
+      tracing::error!(
+        event = "password_reset_request_failure",
+        user_uuid = %user.uuid,
+        reason = "send_email_failed",
+      );
+    }
+  });
+}
+
+fn success_response(event: &str) -> (StatusCode, Json<PasswordResetResponse>) {
+  tracing::info!(event = event);
+  (StatusCode::OK, Json(PasswordResetResponse {
+    error_code: None,
+    policy_error: None,
+  }))
+}
+
+fn warn_response(status_code: StatusCode, error_code: PasswordResetError) -> (StatusCode, Json<PasswordResetResponse>) {
+  tracing::warn!(
+    event = "password_reset_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(PasswordResetResponse {
+    error_code: Some(error_code),
+    policy_error: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<PasswordResetResponse>) {
+  tracing::error!(
+    event = "password_reset_failure",
+    error_code = ?(PasswordResetError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(PasswordResetResponse {
+    error_code: Some(PasswordResetError::InternalError),
+    policy_error: None,
+  }))
+}
+
+/*** Handlers ***/
+
+// Always answers OK, so it can't be used to find out which emails have an account
+pub async fn handle_forgot(State(app_state): State<AppState>, Json(payload): Json<ForgotRequest>) -> (StatusCode, Json<PasswordResetResponse>) {
+  match email_link_db::try_start_resend_cooldown(&app_state.redis_pool, PASSWORD_RESET, &payload.email, app_state.password_reset_resend_sec).await {
+    Ok(true) => {},
+    Ok(false) => return success_response("password_reset_request_throttled"),
+    Err(_) => {
+      tracing::error!(
+        event = "password_reset_request_failure",
+        reason = "cannot_connect_to_redis",
+      );
+      return success_response("password_reset_request");
+    },
+  }
+
+  match user_data::get_user_by_email(&app_state.pool, &payload.email).await {
+    Ok(Some(user)) => send_reset_email_in_background(&app_state, user),
+    Ok(None) => {},
+    Err(_) => {
+      tracing::error!(
+        event = "password_reset_request_failure",
+        reason = "db_error",
+      );
+    },
+  }
+  success_response("password_reset_request")
+}
+
+pub async fn handle_reset(State(app_state): State<AppState>, Json(payload): Json<ResetRequest>) -> (StatusCode, Json<PasswordResetResponse>) {
+  let token_hash = hash_token(&payload.token);
+  let uuid = match password_reset::get_reset_token(&app_state.redis_pool, &token_hash).await {
+    Ok(Some(uuid)) => uuid,
+    Ok(None) => return warn_response(StatusCode::UNAUTHORIZED, PasswordResetError::InvalidToken),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  };
+
+  let user = match user_data::get_user_by_uuid(&app_state.pool, &uuid).await {
+    Ok(Some(user)) => user,
+    Ok(None) => return warn_response(StatusCode::UNAUTHORIZED, PasswordResetError::InvalidToken),
+    Err(_) => return internal_error_response("db_error"),
+  };
+
+  // Checked before the token is used, so the user can try another password with the same link
+  if let Err(policy_error) = app_state.password_policy.check(&payload.password, &user.email, &[&user.name]) {
+    tracing::warn!(
+      event = "password_reset_failure",
+      error_code = ?(PasswordResetError::WeakPassword),
+      policy_error = ?(policy_error),
+    );
+    return (StatusCode::BAD_REQUEST, Json(PasswordResetResponse {
+      error_code: Some(PasswordResetError::WeakPassword),
+      policy_error: Some(policy_error),
+    }));
+  }
+  match password_reset::consume_reset_token(&app_state.redis_pool, &token_hash).await {
+    Ok(Some(consumed_uuid)) if consumed_uuid == uuid => {},
+    Ok(_) => return warn_response(StatusCode::UNAUTHORIZED, PasswordResetError::InvalidToken),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  let Ok(password_hash) = hash_password(&payload.password) else {
+    return internal_error_response("argon2_password_hashing_failed");
+  };
+  if user_data::change_password(&app_state.pool, &user.uuid, &password_hash).await.is_err() {
+    return internal_error_response("db_error");
+  }
+
+  // Sign out everywhere, the old password might be how someone else got in
+  if sessions::revoke_all_sessions(&app_state, &user.uuid).await.is_err() {
+    return internal_error_response("session_revoke_failed");
+  }
+  let _ = sign_in_attempts::clear_sign_in_attempts(&app_state.redis_pool, &user.email).await;
+
+  success_response("password_reset_success")
+}
@@ -0,0 +1,114 @@
+use phonenumber::{country, metadata::DATABASE, Mode, Type};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhoneKind {
+  Mobile,
+  Landline,
+  MobileOrLandline, // Some regions (e.g. the US) can't tell them apart by the number
+  Other,
+}
+
+pub struct PhoneNumber {
+  pub e164: String,    // What gets stored and sent to Vonage, e.g. +972501234567
+  pub country: String, // ISO 3166-1 alpha-2, e.g. IL
+  pub kind: PhoneKind,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PhoneNumberError {
+  InvalidNumber,
+  CountryNotAllowed,
+  NotMobileNumber,
+}
+
+// Numbers without a +country prefix are read as local to `default_country`
+pub fn parse_phone_number(input: &str, default_country: &str) -> Result<PhoneNumber, PhoneNumberError> {
+  let region = default_country.parse::<country::Id>().ok();
+  let number = phonenumber::parse(region, input).map_err(|_| PhoneNumberError::InvalidNumber)?;
+  if !number.is_valid() {
+    return Err(PhoneNumberError::InvalidNumber);
+  }
+  let country = number.metadata(&DATABASE).map(|metadata| metadata.id().to_string())
+    .ok_or(PhoneNumberError::InvalidNumber)?;
+  let kind = match number.number_type(&DATABASE) {
+    Type::Mobile => PhoneKind::Mobile,
+    Type::FixedLine => PhoneKind::Landline,
+    Type::FixedLineOrMobile => PhoneKind::MobileOrLandline,
+    _ => PhoneKind::Other,
+  };
+
+  Ok(PhoneNumber {
+    e164: number.format().mode(Mode::E164).to_string(),
+    country,
+    kind,
+  })
+}
+
+// For numbers we're about to text, checked before paying for the SMS
+pub fn parse_sms_number(input: &str, default_country: &str, allowed_countries: &[String]) -> Result<PhoneNumber, PhoneNumberError> {
+  let number = parse_phone_number(input, default_country)?;
+  if !allowed_countries.iter().any(|allowed| allowed.eq_ignore_ascii_case(&number.country)) {
+    return Err(PhoneNumberError::CountryNotAllowed);
+  }
+  if !matches!(number.kind, PhoneKind::Mobile | PhoneKind::MobileOrLandline) {
+    return Err(PhoneNumberError::NotMobileNumber);
+  }
+  Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn allowed(countries: &[&str]) -> Vec<String> {
+    countries.iter().map(|country| country.to_string()).collect()
+  }
+
+  #[test]
+  fn local_il_input_is_normalized() {
+    for input in ["0522345678", "052-234-5678", "052 234 5678"] {
+      let number = parse_phone_number(input, "IL").unwrap();
+      assert_eq!(number.e164, "+972522345678");
+      assert_eq!(number.country, "IL");
+      assert_eq!(number.kind, PhoneKind::Mobile);
+    }
+  }
+
+  #[test]
+  fn international_input_ignores_the_default_country() {
+    for input in ["+972522345678", "+972 52-234-5678"] {
+      let number = parse_phone_number(input, "US").unwrap();
+      assert_eq!(number.e164, "+972522345678");
+      assert_eq!(number.country, "IL");
+    }
+  }
+
+  #[test]
+  fn landline_is_classified_and_refused_for_sms() {
+    let number = parse_phone_number("02-623-4567", "IL").unwrap();
+    assert_eq!(number.e164, "+97226234567");
+    assert_eq!(number.kind, PhoneKind::Landline);
+    assert_eq!(parse_sms_number("02-623-4567", "IL", &allowed(&["IL"])).err(), Some(PhoneNumberError::NotMobileNumber));
+  }
+
+  #[test]
+  fn mobile_in_allowed_country_is_accepted_for_sms() {
+    let number = parse_sms_number("0522345678", "IL", &allowed(&["il"])).unwrap();
+    assert_eq!(number.e164, "+972522345678");
+  }
+
+  #[test]
+  fn disallowed_country_is_refused_for_sms() {
+    let number = parse_phone_number("+447400123456", "IL").unwrap();
+    assert_eq!(number.country, "GB");
+    assert_eq!(number.kind, PhoneKind::Mobile);
+    assert_eq!(parse_sms_number("+447400123456", "IL", &allowed(&["IL"])).err(), Some(PhoneNumberError::CountryNotAllowed));
+  }
+
+  #[test]
+  fn garbage_input_is_invalid() {
+    for input in ["", "hello", "12", "+972", "05223456788901234"] {
+      assert_eq!(parse_phone_number(input, "IL").err(), Some(PhoneNumberError::InvalidNumber), "{input}");
+    }
+  }
+}
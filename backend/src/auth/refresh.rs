@@ -0,0 +1,169 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth::jwt::{self, JWTError};
+use crate::auth::hashing::{generate_token, hash_token};
+use crate::auth::client_info::ClientInfo;
+use crate::auth::sessions;
+use crate::auth::db::session;
+use crate::auth::db::refresh_token::{self, RefreshTokenUse};
+use crate::auth::db::token_version;
+use crate::auth::db::user_data::{self, UserData};
+use crate::auth::roles;
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+  refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct RefreshResponse {
+  error_code: Option<RefreshError>,
+  jwt_token: Option<String>,
+  refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshError {
+  InvalidToken,
+  TokenReused,
+  InternalError,
+}
+
+pub struct TokenPair {
+  pub access_token: String,
+  pub refresh_token: String,
+}
+
+/*** Helpers ***/
+
+fn refresh_expiration_sec(app_state: &AppState) -> u64 {
+  app_state.refresh_token_expiration_days * 60 * 60 * 24
+}
+
+// Roles are read from the user row on every issue, so grants and revokes apply from the next refresh
+async fn issue_in_family(app_state: &AppState, user: &UserData, family_id: &Uuid, token_version: u64) -> Result<TokenPair, JWTError> {
+  let roles = roles::parse_roles(&user.roles);
+  let access_token = jwt::create_jwt_token(&user.uuid, family_id, token_version, &roles, &app_state.jwt_keys, app_state.access_token_expiration_sec)?;
+  let refresh_token = generate_token();
+  refresh_token::store_token(&app_state.redis_pool, &hash_token(&refresh_token), family_id, refresh_expiration_sec(app_state)).await
+    .map_err(|_| JWTError::InternalError)?;
+  Ok(TokenPair { access_token, refresh_token })
+}
+
+// Starts a new refresh token family and records it as a session, one per sign-in
+pub async fn issue_tokens(app_state: &AppState, user: &UserData, method: &str, client: &ClientInfo) -> Result<TokenPair, JWTError> {
+  let token_version = token_version::get_token_version(&app_state.redis_pool, &user.uuid).await
+    .map_err(|_| JWTError::InternalError)?;
+  let family_id = refresh_token::create_family(&app_state.redis_pool, &user.uuid, token_version, refresh_expiration_sec(app_state)).await
+    .map_err(|_| JWTError::InternalError)?;
+  session::create_session(
+    &app_state.pool,
+    &family_id,
+    &user.uuid,
+    method,
+    client.user_agent.as_deref(),
+    client.ip.as_deref(),
+    refresh_expiration_sec(app_state) as i64,
+  ).await.map_err(|_| JWTError::InternalError)?;
+  issue_in_family(app_state, user, &family_id, token_version).await
+}
+
+fn warn_response(status_code: StatusCode, error_code: RefreshError) -> (StatusCode, Json<RefreshResponse>) {
+  tracing::warn!(
+    event = "refresh_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(RefreshResponse {
+    error_code: Some(error_code),
+    jwt_token: None,
+    refresh_token: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<RefreshResponse>) {
+  tracing::error!(
+    event = "refresh_failure",
+    error_code = ?(RefreshError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(RefreshResponse {
+    error_code: Some(RefreshError::InternalError),
+    jwt_token: None,
+    refresh_token: None,
+  }))
+}
+
+/*** Handlers ***/
+
+pub async fn handle_refresh(State(app_state): State<AppState>, Json(payload): Json<RefreshRequest>) -> (StatusCode, Json<RefreshResponse>) {
+  let family_id = match refresh_token::use_token(&app_state.redis_pool, &hash_token(&payload.refresh_token), refresh_expiration_sec(&app_state)).await {
+    Ok(RefreshTokenUse::Valid(family_id)) => family_id,
+    Ok(RefreshTokenUse::Reused(family_id)) => {
+      // Either the client or an attacker holds a stolen copy, sign the device out for both
+      if let Ok(Some(family)) = refresh_token::get_family(&app_state.redis_pool, &family_id).await
+        && sessions::revoke_session(&app_state, &family.user_uuid, &family_id).await.is_err() {
+        return internal_error_response("session_revoke_failed");
+      }
+      if refresh_token::revoke_family(&app_state.redis_pool, &family_id).await.is_err() {
+        return internal_error_response("cannot_connect_to_redis");
+      }
+      tracing::warn!(
+        event = "refresh_token_reuse_detected",
+        family_id = %family_id,
+      );
+      return warn_response(StatusCode::UNAUTHORIZED, RefreshError::TokenReused);
+    },
+    Ok(RefreshTokenUse::Unknown) => return warn_response(StatusCode::UNAUTHORIZED, RefreshError::InvalidToken),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  };
+
+  let family = match refresh_token::get_family(&app_state.redis_pool, &family_id).await {
+    Ok(Some(family)) => family,
+    Ok(None) => return warn_response(StatusCode::UNAUTHORIZED, RefreshError::InvalidToken),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  };
+
+  // Families started before a password reset are dead
+  match token_version::get_token_version(&app_state.redis_pool, &family.user_uuid).await {
+    Ok(version) if family.token_version < version => {
+      let _ = refresh_token::revoke_family(&app_state.redis_pool, &family_id).await;
+      return warn_response(StatusCode::UNAUTHORIZED, RefreshError::InvalidToken);
+    },
+    Ok(_) => {},
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  let user = match user_data::get_user_by_uuid(&app_state.pool, &family.user_uuid).await {
+    Ok(Some(user)) => user,
+    Ok(None) => return warn_response(StatusCode::UNAUTHORIZED, RefreshError::InvalidToken),
+    Err(_) => return internal_error_response("db_error"),
+  };
+
+  if session::touch_session(&app_state.pool, &family_id, refresh_expiration_sec(&app_state) as i64).await.is_err() {
+    return internal_error_response("db_error");
+  }
+
+  match issue_in_family(&app_state, &user, &family_id, family.token_version).await {
+    Ok(tokens) => {
+      tracing::debug!(
+        event = "refresh_success",
+        user_uuid = %family.user_uuid,
+      );
+      (StatusCode::OK, Json(RefreshResponse {
+        error_code: None,
+        jwt_token: Some(tokens.access_token),
+        refresh_token: Some(tokens.refresh_token),
+      }))
+    },
+    Err(_) => internal_error_response("token_issue_failed"),
+  }
+}
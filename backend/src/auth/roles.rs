@@ -0,0 +1,80 @@
+use std::marker::PhantomData;
+use axum::{
+  extract::FromRequestParts,
+  http::request::Parts,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::{AuthClaims, AuthError, AuthRejection};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+  Admin,
+  Support,
+}
+
+impl Role {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Role::Admin => "admin",
+      Role::Support => "support",
+    }
+  }
+
+  pub fn parse(role: &str) -> Option<Role> {
+    match role {
+      "admin" => Some(Role::Admin),
+      "support" => Some(Role::Support),
+      _ => None,
+    }
+  }
+}
+
+// Unknown role names in the db are ignored rather than failing the sign in
+pub fn parse_roles(roles: &[String]) -> Vec<Role> {
+  roles.iter().filter_map(|role| Role::parse(role)).collect()
+}
+
+/*** Guards ***/
+
+pub trait RequiredRole {
+  fn allows(role: Role) -> bool;
+}
+
+pub struct Admin;
+pub struct Support;
+
+impl RequiredRole for Admin {
+  fn allows(role: Role) -> bool {
+    role == Role::Admin
+  }
+}
+
+// Admins can do everything support staff can
+impl RequiredRole for Support {
+  fn allows(role: Role) -> bool {
+    matches!(role, Role::Support | Role::Admin)
+  }
+}
+
+// Extractor guard, e.g. `RequireRole<Admin>` as a handler argument. To guard a whole router use
+// `middleware::from_extractor_with_state::<RequireRole<Admin>, _>(app_state)` as a route layer.
+// Roles come from the access token, so changes apply from the next token refresh.
+pub struct RequireRole<R> {
+  pub auth: AuthClaims,
+  _role: PhantomData<R>,
+}
+
+impl<R: RequiredRole> FromRequestParts<AppState> for RequireRole<R> {
+  type Rejection = AuthRejection;
+
+  async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+    let auth = AuthClaims::from_request_parts(parts, state).await?;
+    if !auth.claims.roles.iter().any(|role| R::allows(*role)) {
+      return Err(AuthRejection(AuthError::Forbidden));
+    }
+    Ok(RequireRole { auth, _role: PhantomData })
+  }
+}
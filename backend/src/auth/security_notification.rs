@@ -0,0 +1,20 @@
+use crate::app_state::AppState;
+use crate::api::send_email::{send_email, escape_html};
+use crate::auth::db::user_data::UserData;
+
+// Lets the owner know about account changes, so they can react if it wasn't them
+pub async fn send_security_notification(app_state: &AppState, user: &UserData, subject: &str, message: &str) -> Result<(), anyhow::Error> {
+  let html_body = format!(
+    "<p>Hi {},</p>\
+     <p>{message}</p>\
+     <p>If this wasn't you, reset your password right away and sign out of all devices.</p>",
+    escape_html(&user.name),
+  );
+
+  send_email(&app_state.resend, &app_state.email_from, vec![&user.email], subject, &html_body).await?;
+  tracing::info!(
+    event = "security_notification_sent",
+    user_uuid = %user.uuid,
+  );
+  Ok(())
+}
@@ -0,0 +1,142 @@
+use axum::{
+  extract::{Json, Path, State},
+  http::StatusCode,
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthClaims;
+use crate::auth::jwt::JWTClaims;
+use crate::auth::db::session::{self, SessionData};
+use crate::auth::db::refresh_token;
+use crate::auth::db::token_version;
+
+/*** Json Structs **/
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+  id: Uuid,
+  method: String,
+  user_agent: Option<String>,
+  ip: Option<String>,
+  created_at: OffsetDateTime,
+  last_used_at: OffsetDateTime,
+  current: bool,
+}
+
+#[derive(Serialize)]
+pub struct SessionsResponse {
+  error_code: Option<SessionsError>,
+  sessions: Option<Vec<SessionInfo>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionsError {
+  SessionNotFound,
+  InternalError,
+}
+
+/*** Helpers ***/
+
+// Marks the session row revoked and kills its refresh token family, which also rejects its access tokens
+pub async fn revoke_session(app_state: &AppState, user_uuid: &Uuid, session_id: &Uuid) -> Result<bool, anyhow::Error> {
+  let revoked = session::revoke_session(&app_state.pool, user_uuid, session_id).await?;
+  if revoked {
+    refresh_token::revoke_family(&app_state.redis_pool, session_id).await?;
+  }
+  Ok(revoked)
+}
+
+// Bumping the token version rejects every token issued so far, the rows are only kept in sync for listing
+pub async fn revoke_all_sessions(app_state: &AppState, user_uuid: &Uuid) -> Result<(), anyhow::Error> {
+  token_version::increment_token_version(&app_state.redis_pool, user_uuid).await?;
+  session::revoke_all_sessions(&app_state.pool, user_uuid).await?;
+  Ok(())
+}
+
+// Used after security changes made from a signed-in device, which stays signed in
+pub async fn revoke_other_sessions(app_state: &AppState, user_uuid: &Uuid, current_session: &Uuid) -> Result<(), anyhow::Error> {
+  for session in session::get_active_sessions(&app_state.pool, user_uuid).await? {
+    if session.id != *current_session {
+      revoke_session(app_state, user_uuid, &session.id).await?;
+    }
+  }
+  Ok(())
+}
+
+// Access tokens get refreshed for weeks, so the session's sign in time is what tells if the user just proved who they are.
+// A missing session counts as not recent.
+pub async fn is_recently_authenticated(app_state: &AppState, claims: &JWTClaims) -> Result<bool, anyhow::Error> {
+  let Some(session) = session::get_session(&app_state.pool, &claims.sid).await? else {
+    return Ok(false);
+  };
+  Ok((OffsetDateTime::now_utc() - session.created_at).whole_seconds() <= app_state.reauth_max_age_sec)
+}
+
+fn session_info(session: SessionData, current_session: &Uuid) -> SessionInfo {
+  SessionInfo {
+    current: session.id == *current_session,
+    id: session.id,
+    method: session.method,
+    user_agent: session.user_agent,
+    ip: session.ip,
+    created_at: session.created_at,
+    last_used_at: session.last_used_at,
+  }
+}
+
+fn warn_response(status_code: StatusCode, error_code: SessionsError) -> (StatusCode, Json<SessionsResponse>) {
+  tracing::warn!(
+    event = "sessions_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(SessionsResponse {
+    error_code: Some(error_code),
+    sessions: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<SessionsResponse>) {
+  tracing::error!(
+    event = "sessions_failure",
+    error_code = ?(SessionsError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(SessionsResponse {
+    error_code: Some(SessionsError::InternalError),
+    sessions: None,
+  }))
+}
+
+/*** Handlers ***/
+
+pub async fn handle_list_sessions(State(app_state): State<AppState>, AuthClaims { user_uuid: uuid, claims }: AuthClaims) -> (StatusCode, Json<SessionsResponse>) {
+  match session::get_active_sessions(&app_state.pool, &uuid).await {
+    Ok(sessions) => (StatusCode::OK, Json(SessionsResponse {
+      error_code: None,
+      sessions: Some(sessions.into_iter().map(|session| session_info(session, &claims.sid)).collect()),
+    })),
+    Err(_) => internal_error_response("db_error"),
+  }
+}
+
+pub async fn handle_revoke_session(State(app_state): State<AppState>, AuthClaims { user_uuid: uuid, .. }: AuthClaims, Path(session_id): Path<Uuid>) -> (StatusCode, Json<SessionsResponse>) {
+  match revoke_session(&app_state, &uuid, &session_id).await {
+    Ok(true) => {
+      tracing::info!(
+        event = "session_revoke_success",
+        user_uuid = %uuid,
+        session_id = %session_id,
+      );
+      (StatusCode::OK, Json(SessionsResponse {
+        error_code: None,
+        sessions: None,
+      }))
+    },
+    Ok(false) => warn_response(StatusCode::NOT_FOUND, SessionsError::SessionNotFound),
+    Err(_) => internal_error_response("session_revoke_failed"),
+  }
+}
@@ -2,19 +2,25 @@ use axum::{
   extract::{Json, State},
   http::StatusCode,
 };
-use axum_extra::{
-    extract::TypedHeader,
-    headers::{Authorization, authorization::Bearer},
-};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use webauthn_rs::prelude::PublicKeyCredential;
 
-use crate::auth::jwt::{self, JWTError};
+use crate::auth::jwt::JWTError;
+use crate::auth::auth_user::AuthUser;
+use crate::auth::refresh;
+use crate::auth::two_factor;
+use crate::auth::passkeys;
+use crate::auth::sms_sign_in;
+use crate::auth::magic_link;
+use crate::auth::client_info::ClientInfo;
 use crate::auth::hashing::verify_password;
 use crate::auth::google_claims::get_google_claims;
 use crate::auth::db::user_data::{self, UserData};
 use crate::auth::db::sign_in_attempts;
 use crate::app_state::AppState;
+use crate::telemetry;
+use crate::redact::{Email, Token};
 
 const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$+1F6v9OZsFvaYlTL8IPwtA$2lf7JtSOvRBZldOVGxWWgw+4uh/09TFFWJF6YGL+9co";
 
@@ -25,32 +31,67 @@ const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$+1F6v9OZsFvaYlTL8IPwtA$
 pub enum SignInRequest {
   PASSWORD { email: String, password: String },
   GOOGLE { id_token: String },
+  #[allow(clippy::upper_case_acronyms)]
+  #[serde(rename = "webauthn")]
+  WEBAUTHN { challenge_id: Uuid, credential: Box<PublicKeyCredential> },
+  #[allow(clippy::upper_case_acronyms)]
+  #[serde(rename = "sms")]
+  SMS { request_id: Uuid, code: String },
+  #[allow(non_camel_case_types)]
+  #[serde(rename = "magic_link")]
+  MAGIC_LINK { token: String, confirmation_code: Option<String> },
 }
 
 #[derive(Serialize)]
 pub struct SignInResponse {
   error_code: Option<SignInError>,
   jwt_token: Option<String>,
+  refresh_token: Option<String>,
   user_data: Option<UserData>,
+  challenge_token: Option<String>, // Set with second_factor_required, exchanged at /auth/sign-in/totp
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SignInError {
   InvalidCredentials,
-  TokenExpired,
   InternalError,
   NeedToVerifyEmail,
+  SecondFactorRequired,
+  InvalidSecondFactor,
+  SecondFactorLocked,
+}
+
+impl SignInError {
+  fn as_str(&self) -> &'static str {
+    match self {
+      SignInError::InvalidCredentials => "invalid_credentials",
+      SignInError::InternalError => "internal_error",
+      SignInError::NeedToVerifyEmail => "need_to_verify_email",
+      SignInError::SecondFactorRequired => "second_factor_required",
+      SignInError::InvalidSecondFactor => "invalid_second_factor",
+      SignInError::SecondFactorLocked => "second_factor_locked",
+    }
+  }
 }
 
 /*** Helpers ***/
 
-async fn success_response(app_state: &AppState, user: UserData, method: &str) -> (StatusCode, Json<SignInResponse>) {
-  match jwt::create_jwt_token(&user.uuid, &app_state.jwt_secret, app_state.jwt_expiration_days) {
-    Ok(jwt_token) => {
+pub async fn success_response(app_state: &AppState, user: UserData, method: &str, client: &ClientInfo) -> (StatusCode, Json<SignInResponse>) {
+  match refresh::issue_tokens(app_state, &user, method, client).await {
+    Ok(tokens) => {
       if user_data::update_last_seen(&app_state.pool, &user.uuid).await.is_err() {
         return internal_error_response();
       };
+      // Signing in during the grace period keeps the account
+      match user_data::cancel_deletion(&app_state.pool, &user.uuid).await {
+        Ok(true) => tracing::info!(
+          event = "delete_account_cancelled",
+          user_uuid = %user.uuid,
+        ),
+        Ok(false) => {},
+        Err(_) => return internal_error_response(),
+      }
       tracing::debug!(
         event = "sign_in_success",
         method = method,
@@ -58,8 +99,10 @@ async fn success_response(app_state: &AppState, user: UserData, method: &str) ->
       );
       (StatusCode::OK, Json(SignInResponse {
         error_code: None,
-        jwt_token: Some(jwt_token),
+        jwt_token: Some(tokens.access_token),
+        refresh_token: Some(tokens.refresh_token),
         user_data: Some(user),
+        challenge_token: None,
       }))
     },
     Err(error) => {
@@ -71,7 +114,7 @@ async fn success_response(app_state: &AppState, user: UserData, method: &str) ->
   }
 }
 
-fn unauthorized_response(code: SignInError) -> (StatusCode, Json<SignInResponse>) {
+pub fn unauthorized_response(code: SignInError) -> (StatusCode, Json<SignInResponse>) {
   tracing::warn!(
     event = "sign_in_failure",
     error_code = ?code,
@@ -79,12 +122,27 @@ fn unauthorized_response(code: SignInError) -> (StatusCode, Json<SignInResponse>
   (StatusCode::UNAUTHORIZED, Json(SignInResponse {
       error_code: Some(code),
       jwt_token: None,
+      refresh_token: None,
       user_data: None,
+      challenge_token: None,
     }),
   )
 }
 
-fn internal_error_response() -> (StatusCode, Json<SignInResponse>) {
+fn second_factor_response(challenge_token: String) -> (StatusCode, Json<SignInResponse>) {
+  tracing::info!(
+    event = "sign_in_second_factor_required",
+  );
+  (StatusCode::UNAUTHORIZED, Json(SignInResponse {
+    error_code: Some(SignInError::SecondFactorRequired),
+    jwt_token: None,
+    refresh_token: None,
+    user_data: None,
+    challenge_token: Some(challenge_token),
+  }))
+}
+
+pub fn internal_error_response() -> (StatusCode, Json<SignInResponse>) {
   tracing::error!(
     event = "sign_in_failure",
     error_code = ?(SignInError::InternalError),
@@ -93,48 +151,60 @@ fn internal_error_response() -> (StatusCode, Json<SignInResponse>) {
   (StatusCode::INTERNAL_SERVER_ERROR, Json(SignInResponse {
     error_code: Some(SignInError::InternalError),
     jwt_token: None,
+    refresh_token: None,
     user_data: None,
+    challenge_token: None,
   }))
 }
 
+pub fn record_outcome(method: &'static str, response: &(StatusCode, Json<SignInResponse>)) {
+  let outcome = response.1.error_code.as_ref().map_or("success", SignInError::as_str);
+  telemetry::record_sign_in(method, outcome);
+}
+
 /*** Handlers ***/
 
-pub async fn handle_sign_in(app_state: State<AppState>, Json(payload): Json<SignInRequest>) -> (StatusCode, Json<SignInResponse>) {
-  match payload {
-    SignInRequest::PASSWORD { email, password } => handle_password_sign_in(app_state, email, password).await,
-    SignInRequest::GOOGLE { id_token } => handle_google_sign_in(app_state, id_token).await,
-  }
+pub async fn handle_sign_in(app_state: State<AppState>, client: ClientInfo, Json(payload): Json<SignInRequest>) -> (StatusCode, Json<SignInResponse>) {
+  let method = match &payload {
+    SignInRequest::PASSWORD { .. } => "password",
+    SignInRequest::GOOGLE { .. } => "google",
+    SignInRequest::WEBAUTHN { .. } => "webauthn",
+    SignInRequest::SMS { .. } => "sms",
+    SignInRequest::MAGIC_LINK { .. } => "magic_link",
+  };
+  let response = match payload {
+    SignInRequest::PASSWORD { email, password } => handle_password_sign_in(app_state, client, email, password).await,
+    SignInRequest::GOOGLE { id_token } => handle_google_sign_in(app_state, client, id_token).await,
+    SignInRequest::WEBAUTHN { challenge_id, credential } => handle_webauthn_sign_in(app_state, client, challenge_id, credential).await,
+    SignInRequest::SMS { request_id, code } => handle_sms_sign_in(app_state, client, request_id, code).await,
+    SignInRequest::MAGIC_LINK { token, confirmation_code } => handle_magic_link_sign_in(app_state, client, token, confirmation_code).await,
+  };
+  record_outcome(method, &response);
+  response
 }
 
-pub async fn handle_jwt_sign_in(State(app_state): State<AppState>, TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>) -> (StatusCode, Json<SignInResponse>) {
-  if let Ok(claims) = jwt::get_jwt_claims(&bearer.token(), &app_state.jwt_secret) {
-    if let Ok(uuid) = Uuid::parse_str(&claims.sub) {
-      let user = user_data::get_user_by_uuid(&app_state.pool, &uuid).await;
-      if let Ok(user) = user {
-        if let Err(_) = user_data::update_last_seen(&app_state.pool, &uuid).await { 
-          return internal_error_response();
-        }
-        tracing::debug!(
-          event = "sign_in_success",
-          method = "jwt",
-          user_uuid = %uuid,
-        );
-        return (StatusCode::OK, Json(SignInResponse {
-          error_code: None, 
-          jwt_token: None,
-          user_data: user,
-        }));
-      }
-    }
-    return unauthorized_response(SignInError::InvalidCredentials);
+pub async fn handle_jwt_sign_in(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser) -> (StatusCode, Json<SignInResponse>) {
+  if user_data::update_last_seen(&app_state.pool, &user.uuid).await.is_err() {
+    return internal_error_response();
   }
-
-  unauthorized_response(SignInError::TokenExpired)
+  tracing::debug!(
+    event = "sign_in_success",
+    method = "jwt",
+    user_uuid = %user.uuid,
+    session_id = %claims.sid,
+  );
+  (StatusCode::OK, Json(SignInResponse {
+    error_code: None,
+    jwt_token: None,
+    refresh_token: None,
+    user_data: Some(user),
+    challenge_token: None,
+  }))
 }
 
 /*** Password ***/
 
-async fn handle_password_sign_in(State(app_state): State<AppState>, email: String, password: String) -> (StatusCode, Json<SignInResponse>) {
+async fn handle_password_sign_in(State(app_state): State<AppState>, client: ClientInfo, email: String, password: String) -> (StatusCode, Json<SignInResponse>) {
   // Block more then x sign in attempts in under 5 minutes
   if let Ok(locked) = sign_in_attempts::is_locked(&app_state.redis_pool, &email, app_state.max_sign_in_attempts).await {
     if locked {
@@ -148,8 +218,17 @@ async fn handle_password_sign_in(State(app_state): State<AppState>, email: Strin
   if let Ok(Some(user)) = user {
     if let Some(password_hash) = &user.password_hash {
       if verify_password(&password, password_hash) {
+        if !user.email_verified {
+          return unauthorized_response(SignInError::NeedToVerifyEmail);
+        }
+        // With TOTP on, the attempts are only cleared once the code checks out too
+        match two_factor::start_challenge(&app_state, &user).await {
+          Ok(Some(challenge_token)) => return second_factor_response(challenge_token),
+          Ok(None) => {},
+          Err(_) => return internal_error_response(),
+        }
         let _ = sign_in_attempts::clear_sign_in_attempts(&app_state.redis_pool, &email).await;
-        return success_response(&app_state, user, "password").await;
+        return success_response(&app_state, user, "password", &client).await;
       }
     }
   }
@@ -164,10 +243,9 @@ async fn handle_password_sign_in(State(app_state): State<AppState>, email: Strin
 
 /*** Google ***/
 
-async fn handle_google_sign_in(State(app_state): State<AppState>, id_token: String) -> (StatusCode, Json<SignInResponse>) {
+async fn handle_google_sign_in(State(app_state): State<AppState>, client: ClientInfo, id_token: String) -> (StatusCode, Json<SignInResponse>) {
   // Getting the claims from google
-  tracing::debug!("Received ID token: {}", id_token);
-  tracing::debug!("client id: {}", &app_state.google_console_client_id);
+  tracing::debug!(event = "google_sign_in_received", id_token = %Token(&id_token));
   let claims = match get_google_claims(&id_token, &app_state.google_console_client_id).await {
     Ok(claims) => claims,
     Err(_) => {
@@ -175,15 +253,20 @@ async fn handle_google_sign_in(State(app_state): State<AppState>, id_token: Stri
       },  
   };
 
-  tracing::debug!("Looking up user by google_sub: {}", claims.sub);
-  tracing::debug!("Looking up user by email: {}", claims.email);
+  tracing::debug!(event = "google_user_lookup", google_sub = claims.sub, email = %Email(&claims.email));
 
   // Try finding user by google_sub
   if let Ok(Some(user)) = user_data::get_user_by_google_sub(&app_state.pool, &claims.sub).await {
     if !claims.email_verified {
       return unauthorized_response(SignInError::NeedToVerifyEmail);
     }
-    return success_response(&app_state, user, "google").await;
+    // Google's own second factor is up to the user, TOTP on this account still applies
+    match two_factor::start_challenge(&app_state, &user).await {
+      Ok(Some(challenge_token)) => return second_factor_response(challenge_token),
+      Ok(None) => {},
+      Err(_) => return internal_error_response(),
+    }
+    return success_response(&app_state, user, "google", &client).await;
   }
 
   // Try linking to existing email. If google sub exist, not auth.
@@ -200,9 +283,74 @@ async fn handle_google_sign_in(State(app_state): State<AppState>, id_token: Stri
         event = "google_sub_link",
         user_uuid = %user.uuid,
       );
-      return success_response(&app_state, user, "google").await;
+      match two_factor::start_challenge(&app_state, &user).await {
+        Ok(Some(challenge_token)) => return second_factor_response(challenge_token),
+        Ok(None) => {},
+        Err(_) => return internal_error_response(),
+      }
+      return success_response(&app_state, user, "google", &client).await;
     }
   }
   
   unauthorized_response(SignInError::InvalidCredentials)
-}
\ No newline at end of file
+}
+/*** WebAuthn ***/
+
+// Passkeys require user verification on the device, so they count as both factors
+async fn handle_webauthn_sign_in(State(app_state): State<AppState>, client: ClientInfo, challenge_id: Uuid, credential: Box<PublicKeyCredential>) -> (StatusCode, Json<SignInResponse>) {
+  match passkeys::verify_assertion(&app_state, &challenge_id, &credential).await {
+    Ok(Some(user)) => {
+      if !user.email_verified {
+        return unauthorized_response(SignInError::NeedToVerifyEmail);
+      }
+      success_response(&app_state, user, "webauthn", &client).await
+    },
+    Ok(None) => unauthorized_response(SignInError::InvalidCredentials),
+    Err(_) => internal_error_response(),
+  }
+}
+
+/*** SMS ***/
+
+async fn handle_sms_sign_in(State(app_state): State<AppState>, client: ClientInfo, request_id: Uuid, code: String) -> (StatusCode, Json<SignInResponse>) {
+  match sms_sign_in::verify_sms_sign_in(&app_state, &request_id, &code).await {
+    Ok(Some(user)) => {
+      if !user.email_verified {
+        return unauthorized_response(SignInError::NeedToVerifyEmail);
+      }
+      // The phone is one factor, users with TOTP still need their code
+      match two_factor::start_challenge(&app_state, &user).await {
+        Ok(Some(challenge_token)) => return second_factor_response(challenge_token),
+        Ok(None) => {},
+        Err(_) => return internal_error_response(),
+      }
+      success_response(&app_state, user, "sms", &client).await
+    },
+    Ok(None) => unauthorized_response(SignInError::InvalidCredentials),
+    Err(_) => internal_error_response(),
+  }
+}
+
+/*** Magic link ***/
+
+async fn handle_magic_link_sign_in(State(app_state): State<AppState>, client: ClientInfo, token: String, confirmation_code: Option<String>) -> (StatusCode, Json<SignInResponse>) {
+  match magic_link::consume_magic_link(&app_state, &token, confirmation_code.as_deref()).await {
+    Ok(Some(mut user)) => {
+      // Opening the link proves the address, same as the verification email
+      if !user.email_verified {
+        if user_data::verify_email(&app_state.pool, &user.uuid, &user.email).await.is_err() {
+          return internal_error_response();
+        }
+        user.email_verified = true;
+      }
+      match two_factor::start_challenge(&app_state, &user).await {
+        Ok(Some(challenge_token)) => return second_factor_response(challenge_token),
+        Ok(None) => {},
+        Err(_) => return internal_error_response(),
+      }
+      success_response(&app_state, user, "magic_link", &client).await
+    },
+    Ok(None) => unauthorized_response(SignInError::InvalidCredentials),
+    Err(_) => internal_error_response(),
+  }
+}
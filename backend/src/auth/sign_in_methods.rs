@@ -0,0 +1,146 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthUser;
+use crate::auth::sessions;
+use crate::auth::hashing::hash_password;
+use crate::auth::password_policy::PasswordPolicyError;
+use crate::auth::security_notification::send_security_notification;
+use crate::auth::db::user_data::{self, UserData};
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct LinkPasswordRequest {
+  password: String,
+}
+
+#[derive(Serialize)]
+pub struct SignInMethodsResponse {
+  error_code: Option<SignInMethodsError>,
+  policy_error: Option<PasswordPolicyError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignInMethodsError {
+  PasswordAlreadySet,
+  WeakPassword,
+  GoogleNotLinked,
+  NoOtherSignInMethod,
+  NeedToReauthenticate,
+  InternalError,
+}
+
+/*** Helpers ***/
+
+async fn notify(app_state: &AppState, user: &UserData, subject: &str, message: &str) {
+  if send_security_notification(app_state, user, subject, message).await.is_err() {
+    tracing::error!(
+      event = "sign_in_methods_notification_failure",
+      user_uuid = %user.uuid,
+      reason = "send_email_failed",
+    );
+  }
+}
+
+fn success_response(event: &str, user: &UserData) -> (StatusCode, Json<SignInMethodsResponse>) {
+  tracing::info!(
+    event = event,
+    user_uuid = %user.uuid,
+  );
+  (StatusCode::OK, Json(SignInMethodsResponse {
+    error_code: None,
+    policy_error: None,
+  }))
+}
+
+fn warn_response(status_code: StatusCode, error_code: SignInMethodsError) -> (StatusCode, Json<SignInMethodsResponse>) {
+  tracing::warn!(
+    event = "sign_in_methods_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(SignInMethodsResponse {
+    error_code: Some(error_code),
+    policy_error: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<SignInMethodsResponse>) {
+  tracing::error!(
+    event = "sign_in_methods_failure",
+    error_code = ?(SignInMethodsError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(SignInMethodsResponse {
+    error_code: Some(SignInMethodsError::InternalError),
+    policy_error: None,
+  }))
+}
+
+/*** Handlers ***/
+
+// Sets a first password on accounts created with Google, changing an existing one goes through /auth/password/change
+pub async fn handle_link_password(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser, Json(payload): Json<LinkPasswordRequest>) -> (StatusCode, Json<SignInMethodsResponse>) {
+  if user.password_hash.is_some() {
+    return warn_response(StatusCode::CONFLICT, SignInMethodsError::PasswordAlreadySet);
+  }
+  // There's no password to confirm yet, a stolen access token alone mustn't be enough to add one
+  match sessions::is_recently_authenticated(&app_state, &claims).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::FORBIDDEN, SignInMethodsError::NeedToReauthenticate),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  if let Err(policy_error) = app_state.password_policy.check(&payload.password, &user.email, &[&user.name]) {
+    tracing::warn!(
+      event = "sign_in_methods_failure",
+      error_code = ?(SignInMethodsError::WeakPassword),
+      policy_error = ?(policy_error),
+    );
+    return (StatusCode::BAD_REQUEST, Json(SignInMethodsResponse {
+      error_code: Some(SignInMethodsError::WeakPassword),
+      policy_error: Some(policy_error),
+    }));
+  }
+
+  let Ok(password_hash) = hash_password(&payload.password) else {
+    return internal_error_response("argon2_password_hashing_failed");
+  };
+  match user_data::link_password(&app_state.pool, &user.uuid, &password_hash).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::CONFLICT, SignInMethodsError::PasswordAlreadySet),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  notify(&app_state, &user, "A password was added to your Getly account", "A password was just added to your Getly account. You can now sign in with your email and password.").await;
+  success_response("password_link_success", &user)
+}
+
+pub async fn handle_unlink_google(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser) -> (StatusCode, Json<SignInMethodsResponse>) {
+  if user.google_sub.is_none() {
+    return warn_response(StatusCode::BAD_REQUEST, SignInMethodsError::GoogleNotLinked);
+  }
+  // Google is the only way in until a password is set
+  if user.password_hash.is_none() {
+    return warn_response(StatusCode::CONFLICT, SignInMethodsError::NoOtherSignInMethod);
+  }
+  match sessions::is_recently_authenticated(&app_state, &claims).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::FORBIDDEN, SignInMethodsError::NeedToReauthenticate),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  match user_data::unlink_google_sub(&app_state.pool, &user.uuid).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::CONFLICT, SignInMethodsError::NoOtherSignInMethod),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  notify(&app_state, &user, "Google was unlinked from your Getly account", "Google sign in was just removed from your Getly account. You can still sign in with your email and password.").await;
+  success_response("google_unlink_success", &user)
+}
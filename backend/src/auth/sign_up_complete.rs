@@ -6,7 +6,10 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::app_state::AppState;
-use crate::auth::jwt;
+use crate::telemetry;
+use crate::auth::refresh;
+use crate::auth::client_info::ClientInfo;
+use crate::auth::verify_email::send_verification_email;
 use crate::auth::db::sign_up_session;
 use crate::auth::db::user_data::{self, UserData};
 
@@ -18,6 +21,7 @@ pub struct Request {
 #[derive(Serialize)]
 pub struct Response {
   jwt_token: Option<String>,
+  refresh_token: Option<String>,
   user_data: Option<UserData>,
   error_code: Option<Error>,
 }
@@ -39,12 +43,13 @@ fn internal_error_response(uuid: Uuid) -> (StatusCode, Json<Response>) {
   );
   (StatusCode::INTERNAL_SERVER_ERROR, Json(Response {
     jwt_token: None,
+    refresh_token: None,
     user_data: None,
     error_code: Some(Error::InternalError),
   }))
 }
 
-pub async fn handle_complete(State(app_state): State<AppState>, Json(payload): Json<Request>) -> (StatusCode, Json<Response>) {
+pub async fn handle_complete(State(app_state): State<AppState>, client: ClientInfo, Json(payload): Json<Request>) -> (StatusCode, Json<Response>) {
   if let Ok(session) = sign_up_session::get_sign_up_session(&app_state.redis_pool, &payload.uuid).await {
     if !session.sms_verified {
       tracing::warn!(
@@ -54,6 +59,7 @@ pub async fn handle_complete(State(app_state): State<AppState>, Json(payload): J
       );
       return (StatusCode::UNAUTHORIZED, Json(Response {
         jwt_token: None,
+        refresh_token: None,
         user_data: None,
         error_code: Some(Error::CodeNotVerified),
       }));
@@ -63,6 +69,13 @@ pub async fn handle_complete(State(app_state): State<AppState>, Json(payload): J
     }
     if let Ok(user_data) = user_data::create_user(&app_state.pool, &session).await {
       if !user_data.email_verified {
+        if send_verification_email(&app_state, &user_data.uuid, &user_data.email).await.is_err() {
+          tracing::error!(
+            event = "sign_up_complete_failure",
+            uuid = %payload.uuid,
+            reason = "send_verification_email_failed",
+          );
+        }
         tracing::warn!(
           event = "sign_up_complete_failure",
           uuid = %payload.uuid,
@@ -70,18 +83,20 @@ pub async fn handle_complete(State(app_state): State<AppState>, Json(payload): J
         );
         return (StatusCode::UNAUTHORIZED, Json(Response {
           jwt_token: None,
+          refresh_token: None,
           user_data: None,
           error_code: Some(Error::EmailNotVerified),
         }));
       }
-      if let Ok(jwt_token) = jwt::create_jwt_token(&user_data.uuid, &app_state.jwt_secret, app_state.jwt_expiration_days)
-      {
+      if let Ok(tokens) = refresh::issue_tokens(&app_state, &user_data, "sign_up", &client).await {
+        telemetry::record_sign_up_step("completed");
         tracing::info!(
           event = "sign_up_complete_success",
           uuid = %payload.uuid,
         );
         return (StatusCode::OK, Json(Response {
-          jwt_token: Some(jwt_token),
+          jwt_token: Some(tokens.access_token),
+          refresh_token: Some(tokens.refresh_token),
           user_data: Some(user_data),
           error_code: None,
         }));
@@ -98,6 +113,7 @@ pub async fn handle_complete(State(app_state): State<AppState>, Json(payload): J
   );
   (StatusCode::UNAUTHORIZED, Json(Response {
     jwt_token: None,
+    refresh_token: None,
     user_data: None,
     error_code: Some(Error::SessionNotFound),
   }))
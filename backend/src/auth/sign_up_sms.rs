@@ -7,9 +7,13 @@ use uuid::Uuid;
 use chrono::Utc;
 
 use crate::app_state::AppState;
+use crate::telemetry;
 use crate::auth::db::sign_up_session;
 use crate::auth::db::sms_code;
-use crate::api::send_sms::{send_sms_code, SmsCodeSendError};
+use crate::auth::db::user_data;
+use crate::auth::hashing::generate_token;
+use crate::auth::phone_number::{parse_sms_number, PhoneNumberError};
+use crate::api::send_sms::{send_sms, send_sms_code, SmsCodeSendError};
 
 /*** Json Structs **/
 
@@ -43,6 +47,8 @@ pub enum SmsRequestError {
   PhoneNumNotMatching,
   InternalError,
   InvalidNumber,
+  CountryNotAllowed,
+  NotMobileNumber,
   APIError,
   SmsAlreadyVerified,
 }
@@ -58,10 +64,33 @@ pub enum SmsVerifyError {
 }
 
 const SMS_MESSAGE: &str = "is your Getly verification code. It will last for 5 minutes";
+const REGISTERED_NUMBER_MESSAGE: &str = "Someone tried to sign up to Getly with this number, which already has an account. \
+  If it was you, sign in instead. Otherwise you can ignore this message.";
 
 /*** Helpers ***/
 
+// A number that already has an account gets a notice instead of a code, and the session gets a code nobody
+// receives. The answer, and a later verify, are then the same whether or not the number is registered.
+async fn send_code_or_notice(app_state: &AppState, uuid: &Uuid, phone_num: &str, registered: bool) -> Result<(), SmsCodeSendError> {
+  if !registered {
+    return send_sms_code(&app_state.redis_pool, uuid, app_state.sms_code_expiration_sec, &app_state.vonage_api_key, &app_state.vonage_api_secret,
+      phone_num, &app_state.company_phone, SMS_MESSAGE).await;
+  }
+  if sms_code::store_code(&app_state.redis_pool, uuid, &generate_token(), app_state.sms_code_expiration_sec).await.is_err() {
+    return Err(SmsCodeSendError::InternalError);
+  }
+  if let Err(error) = send_sms(uuid, &app_state.vonage_api_key, &app_state.vonage_api_secret, phone_num, &app_state.company_phone, REGISTERED_NUMBER_MESSAGE).await {
+    tracing::error!(
+      event = "sign_up_registered_number_notice_failure",
+      uuid = %uuid,
+      error = ?error,
+    );
+  }
+  Ok(())
+}
+
 fn success_send_response(uuid: Uuid) -> (StatusCode, Json<SmsRequestResponse>) {
+  telemetry::record_sign_up_step("sms_sent");
   tracing::info!(
     event = "sign_up_sms_send_success",
     uuid = %uuid,
@@ -92,6 +121,7 @@ fn error_send_response(status_code: StatusCode, error_code: SmsRequestError) ->
 }
 
 fn success_verify_response(uuid: Uuid) -> (StatusCode, Json<SmsVerifyResponse>) {
+  telemetry::record_sign_up_step("sms_verified");
   tracing::info!(
     event = "sign_up_sms_verify_success",
     uuid = %uuid,
@@ -124,18 +154,28 @@ fn error_verify_response(status_code: StatusCode, error_code: SmsVerifyError) ->
 /*** Handlers ***/
 
 pub async fn handle_sms_request(app_state: State<AppState>, Json(payload): Json<SmsRequest>) -> (StatusCode, Json<SmsRequestResponse>) {
+  // Only the normalized number is compared, stored and texted
+  let phone_num = match parse_sms_number(&payload.phone_num, &app_state.phone_default_country, &app_state.phone_allowed_countries) {
+    Ok(number) => number.e164,
+    Err(PhoneNumberError::InvalidNumber) => return warn_send_response(StatusCode::UNPROCESSABLE_ENTITY, SmsRequestError::InvalidNumber),
+    Err(PhoneNumberError::CountryNotAllowed) => return warn_send_response(StatusCode::UNPROCESSABLE_ENTITY, SmsRequestError::CountryNotAllowed),
+    Err(PhoneNumberError::NotMobileNumber) => return warn_send_response(StatusCode::UNPROCESSABLE_ENTITY, SmsRequestError::NotMobileNumber),
+  };
   if let Ok(session) = sign_up_session::get_sign_up_session(&app_state.redis_pool, &payload.uuid).await {
+    let registered = match user_data::get_user_by_phone(&app_state.pool, &phone_num).await {
+      Ok(user) => user.is_some(),
+      Err(_) => return error_send_response(StatusCode::INTERNAL_SERVER_ERROR, SmsRequestError::InternalError),
+    };
     if session.sms_verified {
       return error_send_response(StatusCode::CONFLICT, SmsRequestError::SmsAlreadyVerified);
     }
-    if let Some(phone_num) = session.phone_num {
-      if payload.phone_num != phone_num.as_str() {
+    if let Some(session_phone_num) = session.phone_num {
+      if phone_num != session_phone_num {
         return warn_send_response(StatusCode::CONFLICT, SmsRequestError::PhoneNumNotMatching);
       }
       if let Some(last_sent_time) = session.sms_sent_at {
         if Utc::now().timestamp() - last_sent_time > app_state.sms_code_resend_sec {
-          if let Err(error) = send_sms_code(&app_state.redis_pool, &payload.uuid, app_state.sms_code_expiration_sec, &app_state.vonage_api_key, &app_state.vonage_api_secret,
-              &payload.phone_num, &app_state.company_phone, SMS_MESSAGE).await {
+          if let Err(error) = send_code_or_notice(&app_state, &payload.uuid, &phone_num, registered).await {
             return match error {
               SmsCodeSendError::APIConnectionError | SmsCodeSendError::APIInternalError | SmsCodeSendError::APIAccountError | SmsCodeSendError::TooManyRequests
                 => error_send_response(StatusCode::BAD_GATEWAY, SmsRequestError::APIError),
@@ -154,11 +194,10 @@ pub async fn handle_sms_request(app_state: State<AppState>, Json(payload): Json<
       }
       return error_send_response(StatusCode::INTERNAL_SERVER_ERROR, SmsRequestError::InternalError);
     } else {
-      if let Err(_) = sign_up_session::link_phone_num(&app_state.redis_pool, &payload.uuid, &payload.phone_num).await {
+      if let Err(_) = sign_up_session::link_phone_num(&app_state.redis_pool, &payload.uuid, &phone_num).await {
         return error_send_response(StatusCode::INTERNAL_SERVER_ERROR, SmsRequestError::InternalError);
       }
-      if let Err(error) = send_sms_code(&app_state.redis_pool, &payload.uuid, app_state.sms_code_expiration_sec, &app_state.vonage_api_key, &app_state.vonage_api_secret,
-          &payload.phone_num, &app_state.company_phone, SMS_MESSAGE).await {
+      if let Err(error) = send_code_or_notice(&app_state, &payload.uuid, &phone_num, registered).await {
         return match error {
           SmsCodeSendError::APIConnectionError | SmsCodeSendError::APIInternalError | SmsCodeSendError::APIAccountError | SmsCodeSendError::TooManyRequests
             => error_send_response(StatusCode::BAD_GATEWAY, SmsRequestError::APIError),
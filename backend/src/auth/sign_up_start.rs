@@ -8,9 +8,12 @@ use uuid::Uuid;
 use crate::auth::hashing::hash_password;
 use crate::auth::google_claims::get_google_claims;
 use crate::auth::captcha::verify_recaptcha;
+use crate::auth::password_policy::PasswordPolicyError;
 use crate::auth::db::user_data;
 use crate::auth::db::sign_up_session;
 use crate::app_state::AppState;
+use crate::telemetry;
+use crate::redact::Email;
 
 #[derive(Deserialize)]
 #[serde(tag = "method", rename_all = "snake_case")]
@@ -23,6 +26,7 @@ pub enum StartRequest {
 pub struct StartResponse {
   sign_up_token: Option<Uuid>,
   error_code: Option<StartError>,
+  policy_error: Option<PasswordPolicyError>, // Set with weak_password
 }
 
 #[derive(Debug, Serialize)]
@@ -30,6 +34,7 @@ pub struct StartResponse {
 pub enum StartError {
   CaptchaVerificationFailed,
   EmailAlreadyExists,
+  WeakPassword,
   InvalidToken,
   InternalError,
 }
@@ -37,6 +42,7 @@ pub enum StartError {
 /*** Helpers ***/
 
 fn success_response(uuid: Uuid) -> (StatusCode, Json<StartResponse>) {
+  telemetry::record_sign_up_step("started");
   tracing::info!(
     event = "sign_up_start_success",
     uuid = %uuid,
@@ -44,32 +50,35 @@ fn success_response(uuid: Uuid) -> (StatusCode, Json<StartResponse>) {
   (StatusCode::OK, Json(StartResponse {
     sign_up_token: Some(uuid),
     error_code: None,
+    policy_error: None,
   }))
 }
 
 fn warn_response(status_code: StatusCode, error_code: StartError, email: &str, reason: &str) -> (StatusCode, Json<StartResponse>) {
   tracing::warn!(
     event = "sign_up_start_failure",
-    email = email,
+    email = %Email(email),
     error_code = ?(error_code),
     reason = reason,
   );
   (status_code, Json(StartResponse {
     sign_up_token: None,
     error_code: Some(error_code),
+    policy_error: None,
   }))
 }
 
 fn error_response(status_code: StatusCode, error_code: StartError, email: &str, reason: &str) -> (StatusCode, Json<StartResponse>) {
   tracing::error!(
     event = "sign_up_start_failure",
-    email = email,
+    email = %Email(email),
     error_code = ?(error_code),
     reason = reason,
   );
   (status_code, Json(StartResponse {
     sign_up_token: None,
     error_code: Some(error_code),
+    policy_error: None,
   }))
 }
 
@@ -122,9 +131,24 @@ async fn handle_password_start(
             }
         }
 
-        // 3. Hash password
+        // 3. Check the password against the policy
+        if let Err(policy_error) = app_state.password_policy.check(&password, &email, &[&name]) {
+            tracing::warn!(
+                event = "sign_up_start_failure",
+                email = %Email(&email),
+                error_code = ?(StartError::WeakPassword),
+                policy_error = ?(policy_error),
+            );
+            return (StatusCode::BAD_REQUEST, Json(StartResponse {
+                sign_up_token: None,
+                error_code: Some(StartError::WeakPassword),
+                policy_error: Some(policy_error),
+            }));
+        }
+
+        // 4. Hash password
         if let Ok(hashed_password) = hash_password(&password) {
-            // 4. Start sign-up session in Redis
+            // 5. Start sign-up session in Redis
             if let Ok(uuid) = sign_up_session::start_sign_up_password(
                 &app_state.redis_pool,
                 &email,
@@ -154,7 +178,7 @@ async fn handle_password_start(
 
 async fn handle_google_start(State(app_state): State<AppState>, id_token: String) -> (StatusCode, Json<StartResponse>) {
   if let Ok(claims) = get_google_claims(&id_token, &app_state.google_console_client_id).await {
-    if let Ok(_) = user_data::get_user_by_email(&app_state.pool, &claims.email).await {
+    if let Ok(Some(_)) = user_data::get_user_by_email(&app_state.pool, &claims.email).await {
       return warn_response(StatusCode::CONFLICT, StartError::EmailAlreadyExists, &claims.email, "sign_up_attempt_with_existing_email");
     }
     if let Ok(uuid) = sign_up_session::start_sign_up_google(&app_state.redis_pool, &claims, app_state.sign_up_session_expiration_sec).await {
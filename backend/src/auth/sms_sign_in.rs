@@ -0,0 +1,139 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::request_id;
+use crate::api::send_sms::send_sms_code;
+use crate::auth::db::sms_code;
+use crate::auth::db::sms_sign_in;
+use crate::auth::db::user_data::{self, UserData};
+use crate::auth::phone_number::parse_sms_number;
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct SmsSignInRequest {
+  phone_num: String,
+}
+
+#[derive(Serialize)]
+pub struct SmsSignInResponse {
+  error_code: Option<SmsSignInError>,
+  request_id: Option<Uuid>, // Sent back with the code to /auth/sign-in with method sms
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmsSignInError {
+  InvalidNumber,
+  NeedToWaitBeforeResend,
+  InternalError,
+}
+
+const SMS_MESSAGE: &str = "is your Getly sign in code. It will last for 5 minutes";
+
+/*** Helpers ***/
+
+// Sent in the background, so unknown numbers answer just as fast as registered ones
+fn send_code_in_background(app_state: &AppState, request_id: Uuid, user: UserData) {
+  let app_state = app_state.clone();
+  request_id::spawn(async move {
+    if sms_sign_in::store_request(&app_state.redis_pool, &request_id, &user.uuid, app_state.sms_code_expiration_sec).await.is_err() {
+      tracing::error!(
+        event = "sms_sign_in_send_failure",
+        user_uuid = %user.uuid,
+        reason = "cannot_connect_to_redis",
+      );
+      return;
+    }
+    if let Err(error) = send_sms_code(&app_state.redis_pool, &request_id, app_state.sms_code_expiration_sec, &app_state.vonage_api_key, &app_state.vonage_api_secret,
+        &user.phone_num, &app_state.company_phone, SMS_MESSAGE).await {
+      tracing::error!(
+        event = "sms_sign_in_send_failure",
+        user_uuid = %user.uuid,
+        error = ?error,
+      );
+      return;
+    }
+    tracing::info!(
+      event = "sms_sign_in_send_success",
+      user_uuid = %user.uuid,
+    );
+  });
+}
+
+// Checks the code of a sign in request, returns the user or None on a wrong, expired or used code
+pub async fn verify_sms_sign_in(app_state: &AppState, request_id: &Uuid, code: &str) -> Result<Option<UserData>, anyhow::Error> {
+  let Some(user_uuid) = sms_sign_in::get_request(&app_state.redis_pool, request_id).await? else {
+    return Ok(None);
+  };
+  if sms_code::get_code_exist(&app_state.redis_pool, request_id).await.is_err() {
+    return Ok(None);
+  }
+  if !sms_code::verify_code(&app_state.redis_pool, request_id, code, app_state.sms_code_max_attemps).await? {
+    return Ok(None);
+  }
+  if !sms_sign_in::consume_request(&app_state.redis_pool, request_id).await? {
+    return Ok(None);
+  }
+  Ok(user_data::get_user_by_uuid(&app_state.pool, &user_uuid).await?)
+}
+
+fn response(status_code: StatusCode, error_code: Option<SmsSignInError>, request_id: Option<Uuid>) -> (StatusCode, Json<SmsSignInResponse>) {
+  (status_code, Json(SmsSignInResponse {
+    error_code,
+    request_id,
+  }))
+}
+
+/*** Handlers ***/
+
+// Answers the same way whether or not the number belongs to an account
+pub async fn handle_send_code(State(app_state): State<AppState>, Json(payload): Json<SmsSignInRequest>) -> (StatusCode, Json<SmsSignInResponse>) {
+  // Rejected on format alone, this says nothing about whether the number has an account
+  let Ok(phone_num) = parse_sms_number(&payload.phone_num, &app_state.phone_default_country, &app_state.phone_allowed_countries).map(|number| number.e164) else {
+    tracing::warn!(
+      event = "sms_sign_in_send_failure",
+      error_code = ?(SmsSignInError::InvalidNumber),
+    );
+    return response(StatusCode::UNPROCESSABLE_ENTITY, Some(SmsSignInError::InvalidNumber), None);
+  };
+  match sms_code::try_start_resend_cooldown(&app_state.redis_pool, &phone_num, app_state.sms_code_resend_sec as u64).await {
+    Ok(true) => {},
+    Ok(false) => {
+      tracing::warn!(
+        event = "sms_sign_in_send_failure",
+        error_code = ?(SmsSignInError::NeedToWaitBeforeResend),
+      );
+      return response(StatusCode::TOO_MANY_REQUESTS, Some(SmsSignInError::NeedToWaitBeforeResend), None);
+    },
+    Err(_) => {
+      tracing::error!(
+        event = "sms_sign_in_send_failure",
+        error_code = ?(SmsSignInError::InternalError),
+        reason = "cannot_connect_to_redis",
+      );
+      return response(StatusCode::INTERNAL_SERVER_ERROR, Some(SmsSignInError::InternalError), None);
+    },
+  }
+
+  let request_id = Uuid::new_v4();
+  match user_data::get_user_by_phone(&app_state.pool, &phone_num).await {
+    Ok(Some(user)) => send_code_in_background(&app_state, request_id, user),
+    Ok(None) => {},
+    Err(_) => {
+      tracing::error!(
+        event = "sms_sign_in_send_failure",
+        error_code = ?(SmsSignInError::InternalError),
+        reason = "db_error",
+      );
+      return response(StatusCode::INTERNAL_SERVER_ERROR, Some(SmsSignInError::InternalError), None);
+    },
+  }
+
+  response(StatusCode::OK, None, Some(request_id))
+}
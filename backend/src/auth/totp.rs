@@ -0,0 +1,70 @@
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, OsRng, Payload};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use rand::Rng;
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+use crate::auth::hashing::hash_token;
+
+const ISSUER: &str = "Getly";
+const SECRET_LENGTH: usize = 20;
+const NONCE_LENGTH: usize = 12;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+pub fn generate_secret() -> Vec<u8> {
+  let bytes: [u8; SECRET_LENGTH] = rand::rng().random();
+  bytes.to_vec()
+}
+
+// Standard authenticator app settings: SHA1, 6 digits, 30 second steps, one step of clock skew
+pub fn build_totp(secret: Vec<u8>, email: &str) -> Result<TOTP, anyhow::Error> {
+  TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, Some(ISSUER.to_string()), email.to_string())
+    .map_err(|error| anyhow::anyhow!("{error}"))
+}
+
+pub fn is_totp_code(code: &str) -> bool {
+  code.len() == 6 && code.chars().all(|c| c.is_ascii_digit())
+}
+
+// The user uuid is bound as associated data, so a secret copied to another row won't decrypt
+pub fn encrypt_secret(key: &[u8; 32], user_uuid: &Uuid, secret: &[u8]) -> Result<String, anyhow::Error> {
+  let cipher = Aes256Gcm::new(key.into());
+  let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+  let ciphertext = cipher.encrypt(&nonce, Payload { msg: secret, aad: user_uuid.as_bytes() })
+    .map_err(|_| anyhow::anyhow!("totp secret encryption failed"))?;
+  let mut stored = nonce.to_vec();
+  stored.extend_from_slice(&ciphertext);
+  Ok(STANDARD.encode(stored))
+}
+
+pub fn decrypt_secret(key: &[u8; 32], user_uuid: &Uuid, stored: &str) -> Result<Vec<u8>, anyhow::Error> {
+  let stored = STANDARD.decode(stored)?;
+  if stored.len() <= NONCE_LENGTH {
+    return Err(anyhow::anyhow!("totp secret is too short"));
+  }
+  let (nonce, ciphertext) = stored.split_at(NONCE_LENGTH);
+  let cipher = Aes256Gcm::new(key.into());
+  cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: user_uuid.as_bytes() })
+    .map_err(|_| anyhow::anyhow!("totp secret decryption failed"))
+}
+
+// Codes look like `1a2b3-c4d5e`, only their hashes are stored
+pub fn generate_recovery_codes() -> Vec<String> {
+  (0..RECOVERY_CODE_COUNT)
+    .map(|_| {
+      let bytes: [u8; 5] = rand::rng().random();
+      let code = hex::encode(bytes);
+      format!("{}-{}", &code[..5], &code[5..])
+    })
+    .collect()
+}
+
+pub fn hash_recovery_code(code: &str) -> String {
+  let normalized: String = code.chars()
+    .filter(|c| c.is_ascii_alphanumeric())
+    .map(|c| c.to_ascii_lowercase())
+    .collect();
+  hash_token(&normalized)
+}
@@ -0,0 +1,289 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthUser;
+use crate::auth::client_info::ClientInfo;
+use crate::auth::hashing::{generate_token, hash_token};
+use crate::auth::security_notification::send_security_notification;
+use crate::auth::sign_in::{self, SignInError, SignInResponse};
+use crate::auth::totp;
+use crate::auth::db::totp::{self as totp_db, TotpFactor};
+use crate::auth::db::sign_in_challenge;
+use crate::auth::db::sign_in_attempts;
+use crate::auth::db::user_data::{self, UserData};
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct TotpCodeRequest {
+  code: String, // 6 digit TOTP code, or a recovery code where noted
+}
+
+#[derive(Deserialize)]
+pub struct TotpSignInRequest {
+  challenge_token: String,
+  code: String, // TOTP or recovery code
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorResponse {
+  error_code: Option<TwoFactorError>,
+  secret: Option<String>,
+  provisioning_uri: Option<String>,
+  recovery_codes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TwoFactorError {
+  AlreadyEnabled,
+  NotEnabled,
+  SetupNotStarted,
+  InvalidCode,
+  TooManyAttempts,
+  InternalError,
+}
+
+enum SecondFactorCheck {
+  Valid,
+  Invalid,
+  Locked,
+}
+
+// A step either side is accepted, so a used code has to be remembered for three steps
+const USED_CODE_EXPIRATION_SEC: u64 = 90;
+
+/*** Helpers ***/
+
+fn decrypt_totp(app_state: &AppState, user: &UserData, factor: &TotpFactor) -> Result<totp_rs::TOTP, anyhow::Error> {
+  let secret = totp::decrypt_secret(&app_state.totp_encryption_key, &user.uuid, &factor.secret_encrypted)?;
+  totp::build_totp(secret, &user.email)
+}
+
+// Accepts a TOTP code or an unused recovery code. Failures count toward a per user lock, so the
+// 6 digit code can't be guessed by starting challenge after challenge.
+async fn verify_second_factor(app_state: &AppState, user: &UserData, factor: &TotpFactor, code: &str) -> Result<SecondFactorCheck, anyhow::Error> {
+  if sign_in_challenge::is_second_factor_locked(&app_state.redis_pool, &user.uuid, app_state.second_factor_max_failures).await? {
+    return Ok(SecondFactorCheck::Locked);
+  }
+  if check_second_factor(app_state, user, factor, code).await? {
+    sign_in_challenge::clear_second_factor_failures(&app_state.redis_pool, &user.uuid).await?;
+    return Ok(SecondFactorCheck::Valid);
+  }
+  sign_in_challenge::record_second_factor_failure(&app_state.redis_pool, &user.uuid, app_state.second_factor_lock_sec).await?;
+  Ok(SecondFactorCheck::Invalid)
+}
+
+async fn check_second_factor(app_state: &AppState, user: &UserData, factor: &TotpFactor, code: &str) -> Result<bool, anyhow::Error> {
+  let code = code.trim();
+  if totp::is_totp_code(code) {
+    if !decrypt_totp(app_state, user, factor)?.check_current(code)? {
+      return Ok(false);
+    }
+    return sign_in_challenge::try_use_totp_code(&app_state.redis_pool, &user.uuid, code, USED_CODE_EXPIRATION_SEC).await;
+  }
+  let used = totp_db::use_recovery_code(&app_state.pool, &user.uuid, &totp::hash_recovery_code(code)).await?;
+  if used {
+    tracing::info!(
+      event = "totp_recovery_code_used",
+      user_uuid = %user.uuid,
+    );
+  }
+  Ok(used)
+}
+
+async fn get_enabled_factor(app_state: &AppState, user: &UserData) -> Result<Option<TotpFactor>, sqlx::Error> {
+  Ok(totp_db::get_factor(&app_state.pool, &user.uuid).await?.filter(|factor| factor.enabled_at.is_some()))
+}
+
+// Called once the first factor checked out. Returns a challenge token if the user has TOTP enabled.
+pub async fn start_challenge(app_state: &AppState, user: &UserData) -> Result<Option<String>, anyhow::Error> {
+  if get_enabled_factor(app_state, user).await?.is_none() {
+    return Ok(None);
+  }
+  let challenge_token = generate_token();
+  sign_in_challenge::store_challenge(&app_state.redis_pool, &hash_token(&challenge_token), &user.uuid, app_state.sign_in_challenge_expiration_sec).await?;
+  Ok(Some(challenge_token))
+}
+
+async fn notify(app_state: &AppState, user: &UserData, subject: &str, message: &str) {
+  if send_security_notification(app_state, user, subject, message).await.is_err() {
+    tracing::error!(
+      event = "two_factor_notification_failure",
+      user_uuid = %user.uuid,
+      reason = "send_email_failed",
+    );
+  }
+}
+
+fn empty_response(error_code: Option<TwoFactorError>) -> TwoFactorResponse {
+  TwoFactorResponse {
+    error_code,
+    secret: None,
+    provisioning_uri: None,
+    recovery_codes: None,
+  }
+}
+
+fn success_response(event: &str, user: &UserData, response: TwoFactorResponse) -> (StatusCode, Json<TwoFactorResponse>) {
+  tracing::info!(
+    event = event,
+    user_uuid = %user.uuid,
+  );
+  (StatusCode::OK, Json(response))
+}
+
+fn warn_response(status_code: StatusCode, error_code: TwoFactorError) -> (StatusCode, Json<TwoFactorResponse>) {
+  tracing::warn!(
+    event = "two_factor_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(empty_response(Some(error_code))))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<TwoFactorResponse>) {
+  tracing::error!(
+    event = "two_factor_failure",
+    error_code = ?(TwoFactorError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(empty_response(Some(TwoFactorError::InternalError))))
+}
+
+/*** Handlers ***/
+
+// Step 1 of enrollment: a new secret for the authenticator app, not active until confirmed
+pub async fn handle_totp_setup(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser) -> (StatusCode, Json<TwoFactorResponse>) {
+  let secret = totp::generate_secret();
+  let Ok(totp) = totp::build_totp(secret.clone(), &user.email) else {
+    return internal_error_response("totp_build_failed");
+  };
+  let Ok(secret_encrypted) = totp::encrypt_secret(&app_state.totp_encryption_key, &user.uuid, &secret) else {
+    return internal_error_response("totp_encryption_failed");
+  };
+
+  match totp_db::store_pending_factor(&app_state.pool, &user.uuid, &secret_encrypted).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::CONFLICT, TwoFactorError::AlreadyEnabled),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  success_response("totp_setup_success", &user, TwoFactorResponse {
+    error_code: None,
+    secret: Some(totp.get_secret_base32()),
+    provisioning_uri: Some(totp.get_url()),
+    recovery_codes: None,
+  })
+}
+
+// Step 2: a first code proves the app was set up, recovery codes are only ever shown here
+pub async fn handle_totp_enable(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser, Json(payload): Json<TotpCodeRequest>) -> (StatusCode, Json<TwoFactorResponse>) {
+  let factor = match totp_db::get_factor(&app_state.pool, &user.uuid).await {
+    Ok(Some(factor)) if factor.enabled_at.is_some() => return warn_response(StatusCode::CONFLICT, TwoFactorError::AlreadyEnabled),
+    Ok(Some(factor)) => factor,
+    Ok(None) => return warn_response(StatusCode::BAD_REQUEST, TwoFactorError::SetupNotStarted),
+    Err(_) => return internal_error_response("db_error"),
+  };
+
+  let code = payload.code.trim();
+  let Ok(totp) = decrypt_totp(&app_state, &user, &factor) else {
+    return internal_error_response("totp_decryption_failed");
+  };
+  if !totp::is_totp_code(code) || !totp.check_current(code).unwrap_or(false) {
+    return warn_response(StatusCode::UNAUTHORIZED, TwoFactorError::InvalidCode);
+  }
+
+  let recovery_codes = totp::generate_recovery_codes();
+  let recovery_code_hashes: Vec<String> = recovery_codes.iter().map(|code| totp::hash_recovery_code(code)).collect();
+  match totp_db::enable_factor(&app_state.pool, &user.uuid, &recovery_code_hashes).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::CONFLICT, TwoFactorError::AlreadyEnabled),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  notify(&app_state, &user, "Two-factor authentication is on", "Two-factor authentication was just turned on for your Getly account.").await;
+  success_response("totp_enable_success", &user, TwoFactorResponse {
+    error_code: None,
+    secret: None,
+    provisioning_uri: None,
+    recovery_codes: Some(recovery_codes),
+  })
+}
+
+// Takes a TOTP or recovery code, so users who lost their authenticator can turn it off
+pub async fn handle_totp_disable(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser, Json(payload): Json<TotpCodeRequest>) -> (StatusCode, Json<TwoFactorResponse>) {
+  let factor = match get_enabled_factor(&app_state, &user).await {
+    Ok(Some(factor)) => factor,
+    Ok(None) => return warn_response(StatusCode::BAD_REQUEST, TwoFactorError::NotEnabled),
+    Err(_) => return internal_error_response("db_error"),
+  };
+
+  match verify_second_factor(&app_state, &user, &factor, &payload.code).await {
+    Ok(SecondFactorCheck::Valid) => {},
+    Ok(SecondFactorCheck::Invalid) => return warn_response(StatusCode::UNAUTHORIZED, TwoFactorError::InvalidCode),
+    Ok(SecondFactorCheck::Locked) => return warn_response(StatusCode::TOO_MANY_REQUESTS, TwoFactorError::TooManyAttempts),
+    Err(_) => return internal_error_response("totp_verification_failed"),
+  }
+  if totp_db::delete_factor(&app_state.pool, &user.uuid).await.is_err() {
+    return internal_error_response("db_error");
+  }
+
+  notify(&app_state, &user, "Two-factor authentication is off", "Two-factor authentication was just turned off for your Getly account.").await;
+  success_response("totp_disable_success", &user, empty_response(None))
+}
+
+// Second half of a password sign in for users with TOTP, exchanges the challenge and a code for tokens
+pub async fn handle_totp_sign_in(app_state: State<AppState>, client: ClientInfo, payload: Json<TotpSignInRequest>) -> (StatusCode, Json<SignInResponse>) {
+  let response = totp_sign_in(app_state, client, payload).await;
+  sign_in::record_outcome("totp", &response);
+  response
+}
+
+async fn totp_sign_in(State(app_state): State<AppState>, client: ClientInfo, Json(payload): Json<TotpSignInRequest>) -> (StatusCode, Json<SignInResponse>) {
+  let challenge_hash = hash_token(&payload.challenge_token);
+  let user_uuid = match sign_in_challenge::get_challenge(&app_state.redis_pool, &challenge_hash).await {
+    Ok(Some(user_uuid)) => user_uuid,
+    Ok(None) => return sign_in::unauthorized_response(SignInError::InvalidCredentials),
+    Err(_) => return sign_in::internal_error_response(),
+  };
+
+  let user = match user_data::get_user_by_uuid(&app_state.pool, &user_uuid).await {
+    Ok(Some(user)) => user,
+    Ok(None) => return sign_in::unauthorized_response(SignInError::InvalidCredentials),
+    Err(_) => return sign_in::internal_error_response(),
+  };
+  let factor = match get_enabled_factor(&app_state, &user).await {
+    Ok(Some(factor)) => factor,
+    Ok(None) => return sign_in::unauthorized_response(SignInError::InvalidCredentials),
+    Err(_) => return sign_in::internal_error_response(),
+  };
+
+  match verify_second_factor(&app_state, &user, &factor, &payload.code).await {
+    Ok(SecondFactorCheck::Valid) => {},
+    Ok(SecondFactorCheck::Locked) => return sign_in::unauthorized_response(SignInError::SecondFactorLocked),
+    Ok(SecondFactorCheck::Invalid) => {
+      let _ = sign_in_challenge::record_failed_attempt(
+        &app_state.redis_pool,
+        &challenge_hash,
+        app_state.sign_in_challenge_max_attempts,
+        app_state.sign_in_challenge_expiration_sec as i64,
+      ).await;
+      return sign_in::unauthorized_response(SignInError::InvalidSecondFactor);
+    },
+    Err(_) => return sign_in::internal_error_response(),
+  }
+
+  // Two requests racing with valid codes still get only one session
+  match sign_in_challenge::consume_challenge(&app_state.redis_pool, &challenge_hash).await {
+    Ok(true) => {
+      let _ = sign_in_attempts::clear_sign_in_attempts(&app_state.redis_pool, &user.email).await;
+      sign_in::success_response(&app_state, user, "password_totp", &client).await
+    },
+    Ok(false) => sign_in::unauthorized_response(SignInError::InvalidCredentials),
+    Err(_) => sign_in::internal_error_response(),
+  }
+}
@@ -0,0 +1,143 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::telemetry;
+use crate::api::send_email::send_email;
+use crate::auth::email_link::{self, EmailLinkError};
+use crate::auth::db::email_link as email_link_db;
+use crate::auth::db::user_data;
+
+/*** Json Structs **/
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+  token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResendRequest {
+  email: String,
+}
+
+#[derive(Serialize)]
+pub struct VerifyEmailResponse {
+  error_code: Option<VerifyEmailError>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyEmailError {
+  InvalidToken,
+  LinkAlreadyUsed,
+  NeedToWaitBeforeResend,
+  InternalError,
+}
+
+const VERIFY_EMAIL_PAGE: &str = "verify-email.html";
+
+/*** Helpers ***/
+
+pub async fn send_verification_email(app_state: &AppState, user_uuid: &Uuid, email: &str) -> Result<(), anyhow::Error> {
+  let token = email_link::create_link_token(
+    &app_state.redis_pool,
+    &app_state.jwt_secret,
+    email_link::VERIFY_EMAIL,
+    user_uuid,
+    email,
+    app_state.email_verification_expiration_sec,
+  ).await.map_err(|error| anyhow::anyhow!("{:?}", error))?;
+
+  let url = email_link::link_url(&app_state.app_base_url, VERIFY_EMAIL_PAGE, &token);
+  let html_body = format!(
+    "<p>Welcome to Getly!</p>\
+     <p>Please confirm your email address by clicking the link below:</p>\
+     <p><a href=\"{url}\">Verify my email</a></p>\
+     <p>The link will last for {} hours. If you didn't sign up, you can ignore this email.</p>",
+    app_state.email_verification_expiration_sec / 3600,
+  );
+
+  send_email(&app_state.resend, &app_state.email_from, vec![email], "Verify your Getly email", &html_body).await?;
+  tracing::info!(
+    event = "verification_email_sent",
+    user_uuid = %user_uuid,
+  );
+  Ok(())
+}
+
+fn success_response(event: &str) -> (StatusCode, Json<VerifyEmailResponse>) {
+  tracing::info!(event = event);
+  (StatusCode::OK, Json(VerifyEmailResponse {
+    error_code: None,
+  }))
+}
+
+fn warn_response(status_code: StatusCode, error_code: VerifyEmailError) -> (StatusCode, Json<VerifyEmailResponse>) {
+  tracing::warn!(
+    event = "verify_email_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(VerifyEmailResponse {
+    error_code: Some(error_code),
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<VerifyEmailResponse>) {
+  tracing::error!(
+    event = "verify_email_failure",
+    error_code = ?(VerifyEmailError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(VerifyEmailResponse {
+    error_code: Some(VerifyEmailError::InternalError),
+  }))
+}
+
+/*** Handlers ***/
+
+pub async fn handle_verify_email(State(app_state): State<AppState>, Json(payload): Json<VerifyEmailRequest>) -> (StatusCode, Json<VerifyEmailResponse>) {
+  let claims = match email_link::consume_link_token(&app_state.redis_pool, &app_state.jwt_secret, email_link::VERIFY_EMAIL, &payload.token).await {
+    Ok(claims) => claims,
+    Err(EmailLinkError::InvalidToken) => return warn_response(StatusCode::UNAUTHORIZED, VerifyEmailError::InvalidToken),
+    Err(EmailLinkError::AlreadyUsed) => return warn_response(StatusCode::GONE, VerifyEmailError::LinkAlreadyUsed),
+    Err(EmailLinkError::InternalError) => return internal_error_response("cannot_connect_to_redis"),
+  };
+
+  // The email might have changed since the link was sent
+  match user_data::verify_email(&app_state.pool, &claims.sub, &claims.email).await {
+    Ok(true) => {
+      telemetry::record_sign_up_step("email_verified");
+      success_response("verify_email_success")
+    },
+    Ok(false) => warn_response(StatusCode::UNAUTHORIZED, VerifyEmailError::InvalidToken),
+    Err(_) => internal_error_response("db_error"),
+  }
+}
+
+// Answers the same way whether or not the email belongs to an unverified account
+pub async fn handle_resend(State(app_state): State<AppState>, Json(payload): Json<ResendRequest>) -> (StatusCode, Json<VerifyEmailResponse>) {
+  match email_link_db::try_start_resend_cooldown(&app_state.redis_pool, email_link::VERIFY_EMAIL, &payload.email, app_state.email_verification_resend_sec).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::TOO_MANY_REQUESTS, VerifyEmailError::NeedToWaitBeforeResend),
+    Err(_) => return internal_error_response("cannot_connect_to_redis"),
+  }
+
+  match user_data::get_user_by_email(&app_state.pool, &payload.email).await {
+    Ok(Some(user)) if !user.email_verified => {
+      if send_verification_email(&app_state, &user.uuid, &user.email).await.is_err() {
+        tracing::error!(
+          event = "verify_email_resend_failure",
+          user_uuid = %user.uuid,
+          reason = "send_email_failed",
+        );
+      }
+      success_response("verify_email_resend_success")
+    },
+    Ok(_) => success_response("verify_email_resend_success"),
+    Err(_) => internal_error_response("db_error"),
+  }
+}
@@ -0,0 +1,181 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use reqwest::Url;
+use uuid::Uuid;
+use webauthn_rs::prelude::{
+  AuthenticationResult, CreationChallengeResponse, DiscoverableAuthentication, DiscoverableKey, Passkey,
+  PasskeyRegistration, PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse, WebauthnError,
+};
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+use crate::auth::db::user_data::UserData;
+
+// The ceremonies only turn inputs into outputs, storage of the states and passkeys is up to the
+// caller. That keeps them runnable against software authenticator test vectors.
+
+const RP_NAME: &str = "Getly";
+
+// The relying party is the frontend the user signs in on, its host is the rp id
+pub fn build_webauthn(app_base_url: &str) -> Result<Webauthn, anyhow::Error> {
+  let origin = Url::parse(app_base_url)?;
+  let rp_id = origin.host_str().ok_or_else(|| anyhow::anyhow!("APP_BASE_URL has no host"))?.to_string();
+  Ok(WebauthnBuilder::new(&rp_id, &origin)?.rp_name(RP_NAME).build()?)
+}
+
+pub fn credential_id_string(passkey: &Passkey) -> String {
+  URL_SAFE_NO_PAD.encode(passkey.cred_id().as_ref())
+}
+
+// Already registered passkeys are excluded, so the same authenticator isn't added twice
+pub fn start_registration(webauthn: &Webauthn, user: &UserData, existing: &[Passkey]) -> Result<(CreationChallengeResponse, PasskeyRegistration), WebauthnError> {
+  let exclude_credentials = existing.iter().map(|passkey| passkey.cred_id().clone()).collect();
+  webauthn.start_passkey_registration(user.uuid, &user.email, &user.name, Some(exclude_credentials))
+}
+
+pub fn finish_registration(webauthn: &Webauthn, credential: &RegisterPublicKeyCredential, state: &PasskeyRegistration) -> Result<Passkey, WebauthnError> {
+  webauthn.finish_passkey_registration(credential, state)
+}
+
+// Discoverable, so the browser offers the user's passkeys without asking for an email first
+pub fn start_authentication(webauthn: &Webauthn) -> Result<(RequestChallengeResponse, DiscoverableAuthentication), WebauthnError> {
+  webauthn.start_discoverable_authentication()
+}
+
+// Which user and credential the assertion claims to be from, to look the passkey up before finishing
+pub fn identify_credential(webauthn: &Webauthn, credential: &PublicKeyCredential) -> Result<(Uuid, String), WebauthnError> {
+  let (user_uuid, credential_id) = webauthn.identify_discoverable_authentication(credential)?;
+  Ok((user_uuid, URL_SAFE_NO_PAD.encode(credential_id)))
+}
+
+// Also moves the passkey's signature counter forward, the caller stores the updated passkey
+pub fn finish_authentication(webauthn: &Webauthn, credential: &PublicKeyCredential, state: DiscoverableAuthentication, passkey: &mut Passkey) -> Result<AuthenticationResult, WebauthnError> {
+  let result = webauthn.finish_discoverable_authentication(credential, state, &[DiscoverableKey::from(&*passkey)])?;
+  passkey.update_credential(&result);
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use time::OffsetDateTime;
+  use webauthn_authenticator_rs::WebauthnAuthenticator;
+  use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+  use webauthn_rs::prelude::Base64UrlSafeData;
+
+  const APP_BASE_URL: &str = "https://getly.app";
+
+  type Authenticator = WebauthnAuthenticator<SoftPasskey>;
+
+  fn test_user() -> UserData {
+    UserData {
+      uuid: Uuid::new_v4(),
+      email: "dana@example.com".to_string(),
+      email_verified: true,
+      name: "Dana Cohen".to_string(),
+      password_hash: None,
+      google_sub: None,
+      phone_num: "+972501234567".to_string(),
+      created_at: OffsetDateTime::now_utc(),
+      last_seen_at: None,
+      picture: None,
+      roles: Vec::new(),
+    }
+  }
+
+  fn origin() -> Url {
+    Url::parse(APP_BASE_URL).unwrap()
+  }
+
+  fn register(webauthn: &Webauthn, user: &UserData) -> (Authenticator, Passkey) {
+    // falsify_uv makes the soft token claim user verification, which passkeys require
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+    let (options, state) = start_registration(webauthn, user, &[]).unwrap();
+    let credential = authenticator.do_registration(origin(), options).unwrap();
+    let passkey = finish_registration(webauthn, &credential, &state).unwrap();
+    (authenticator, passkey)
+  }
+
+  // SoftPasskey can't keep discoverable credentials, so the credential is named in allowCredentials
+  // like a browser would after the user picked it, and the user handle a passkey returns is set by hand
+  fn assert_with(authenticator: &mut Authenticator, options: RequestChallengeResponse, passkey: &Passkey, user_handle: &Uuid) -> PublicKeyCredential {
+    let mut options = serde_json::to_value(options).unwrap();
+    options["publicKey"]["allowCredentials"] = serde_json::json!([{
+      "type": "public-key",
+      "id": credential_id_string(passkey),
+    }]);
+    let options: RequestChallengeResponse = serde_json::from_value(options).unwrap();
+
+    let mut credential = authenticator.do_authentication(origin(), options).unwrap();
+    credential.response.user_handle = Some(Base64UrlSafeData::from(user_handle.as_bytes().to_vec()));
+    credential
+  }
+
+  fn counter(passkey: &Passkey) -> u64 {
+    serde_json::to_value(passkey).unwrap()["cred"]["counter"].as_u64().unwrap()
+  }
+
+  #[test]
+  fn register_then_authenticate() {
+    let webauthn = build_webauthn(APP_BASE_URL).unwrap();
+    let user = test_user();
+    let (mut authenticator, mut passkey) = register(&webauthn, &user);
+
+    let (options, state) = start_authentication(&webauthn).unwrap();
+    let credential = assert_with(&mut authenticator, options, &passkey, &user.uuid);
+
+    let (user_uuid, credential_id) = identify_credential(&webauthn, &credential).unwrap();
+    assert_eq!(user_uuid, user.uuid);
+    assert_eq!(credential_id, credential_id_string(&passkey));
+    assert!(finish_authentication(&webauthn, &credential, state, &mut passkey).is_ok());
+  }
+
+  #[test]
+  fn replayed_assertion_is_refused() {
+    let webauthn = build_webauthn(APP_BASE_URL).unwrap();
+    let user = test_user();
+    let (mut authenticator, mut passkey) = register(&webauthn, &user);
+
+    let (options, state) = start_authentication(&webauthn).unwrap();
+    let credential = assert_with(&mut authenticator, options, &passkey, &user.uuid);
+    finish_authentication(&webauthn, &credential, state, &mut passkey).unwrap();
+
+    // The state is consumed by the first finish, a new challenge doesn't match the old assertion
+    let (_, new_state) = start_authentication(&webauthn).unwrap();
+    assert!(finish_authentication(&webauthn, &credential, new_state, &mut passkey).is_err());
+  }
+
+  #[test]
+  fn assertion_from_another_users_credential_is_refused() {
+    let webauthn = build_webauthn(APP_BASE_URL).unwrap();
+    let attacker = test_user();
+    let victim = test_user();
+    let (mut attacker_authenticator, attacker_passkey) = register(&webauthn, &attacker);
+    let (_, mut victim_passkey) = register(&webauthn, &victim);
+
+    // The user handle isn't signed, so it can name the victim
+    let (options, state) = start_authentication(&webauthn).unwrap();
+    let credential = assert_with(&mut attacker_authenticator, options, &attacker_passkey, &victim.uuid);
+
+    let (user_uuid, credential_id) = identify_credential(&webauthn, &credential).unwrap();
+    assert_eq!(user_uuid, victim.uuid);
+    // verify_assertion looks the passkey up by user and credential id, which finds none for the victim
+    assert_ne!(credential_id, credential_id_string(&victim_passkey));
+    assert!(finish_authentication(&webauthn, &credential, state, &mut victim_passkey).is_err());
+  }
+
+  #[test]
+  fn finish_authentication_updates_the_counter() {
+    let webauthn = build_webauthn(APP_BASE_URL).unwrap();
+    let user = test_user();
+    let (mut authenticator, mut passkey) = register(&webauthn, &user);
+    assert_eq!(counter(&passkey), 0);
+
+    for expected in 1..=2 {
+      let (options, state) = start_authentication(&webauthn).unwrap();
+      let credential = assert_with(&mut authenticator, options, &passkey, &user.uuid);
+      let result = finish_authentication(&webauthn, &credential, state, &mut passkey).unwrap();
+      assert_eq!(result.counter(), expected);
+      assert_eq!(counter(&passkey), u64::from(expected));
+    }
+  }
+}
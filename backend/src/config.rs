@@ -0,0 +1,586 @@
+use std::env::var;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use serde::Deserialize;
+use toml::{Table, Value};
+
+// Settings are merged from, lowest to highest priority:
+// 1. the defaults below, which depend on the profile (APP_PROFILE=dev|staging|prod, dev if unset)
+// 2. the top level keys of the TOML file at CONFIG_FILE (config.toml if unset, optional)
+// 3. the [profile.<name>] table of that file for the active profile
+// 4. environment variables, named like the key in upper case (database_url -> DATABASE_URL)
+// Everything is checked in one pass and all the problems are reported together.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+  Dev,
+  Staging,
+  Prod,
+}
+
+impl Profile {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Profile::Dev => "dev",
+      Profile::Staging => "staging",
+      Profile::Prod => "prod",
+    }
+  }
+
+  fn parse(profile: &str) -> Option<Profile> {
+    match profile {
+      "dev" => Some(Profile::Dev),
+      "staging" => Some(Profile::Staging),
+      "prod" => Some(Profile::Prod),
+      _ => None,
+    }
+  }
+}
+
+// Keeps keys and passwords out of logs, `{:?}` prints a placeholder instead of the value
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+  pub fn expose(&self) -> &str {
+    &self.0
+  }
+}
+
+impl fmt::Debug for Secret {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("[redacted]")
+  }
+}
+
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<String>);
+
+impl fmt::Display for ConfigErrors {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "Invalid configuration ({} problems):", self.0.len())?;
+    for error in &self.0 {
+      writeln!(f, "  - {error}")?;
+    }
+    Ok(())
+  }
+}
+
+/*** Env parsing ***/
+
+trait FromEnv: Sized {
+  fn from_env(value: &str) -> Result<Self, String>;
+}
+
+macro_rules! from_env_with_parse {
+  ($($ty:ty),*) => {
+    $(impl FromEnv for $ty {
+      fn from_env(value: &str) -> Result<Self, String> {
+        value.trim().parse().map_err(|error| format!("{error}"))
+      }
+    })*
+  };
+}
+
+from_env_with_parse!(bool, u8, u32, u64, i64, usize, SocketAddr);
+
+impl FromEnv for String {
+  fn from_env(value: &str) -> Result<Self, String> {
+    Ok(value.to_string())
+  }
+}
+
+impl FromEnv for Secret {
+  fn from_env(value: &str) -> Result<Self, String> {
+    Ok(Secret(value.to_string()))
+  }
+}
+
+// Lists are comma separated in env vars, e.g. PHONE_ALLOWED_COUNTRIES=IL,US
+impl FromEnv for Vec<String> {
+  fn from_env(value: &str) -> Result<Self, String> {
+    Ok(value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect())
+  }
+}
+
+fn env_name(key: &str) -> String {
+  key.to_uppercase()
+}
+
+// Looks up an env var by name, `var` outside of tests
+type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+fn read_env<T: FromEnv>(key: &str, env: Env, errors: &mut Vec<String>) -> Option<T> {
+  let name = env_name(key);
+  let value = env(&name)?;
+  match T::from_env(&value) {
+    Ok(value) => Some(value),
+    Err(error) => {
+      errors.push(format!("env {name}: {error}"));
+      None
+    },
+  }
+}
+
+fn read_toml<T: for<'de> Deserialize<'de>>(table: &Table, key: &str, source: &str, errors: &mut Vec<String>) -> Option<T> {
+  let value = table.get(key)?;
+  match value.clone().try_into() {
+    Ok(value) => Some(value),
+    Err(error) => {
+      errors.push(format!("{source}: {key}: {}", error.message()));
+      None
+    },
+  }
+}
+
+/*** Fields ***/
+
+macro_rules! config {
+  ($($name:ident: $ty:ty,)*) => {
+    #[derive(Debug, Clone)]
+    pub struct Config {
+      pub profile: Profile,
+      $(pub $name: $ty,)*
+    }
+
+    #[derive(Default)]
+    struct RawConfig {
+      $($name: Option<$ty>,)*
+    }
+
+    impl RawConfig {
+      const KEYS: &'static [&'static str] = &[$(stringify!($name),)*];
+
+      fn merge(&mut self, other: RawConfig) {
+        $(if other.$name.is_some() {
+          self.$name = other.$name;
+        })*
+      }
+
+      fn from_table(table: &Table, source: &str, errors: &mut Vec<String>) -> RawConfig {
+        RawConfig {
+          $($name: read_toml(table, stringify!($name), source, errors),)*
+        }
+      }
+
+      fn from_env(env: Env, errors: &mut Vec<String>) -> RawConfig {
+        RawConfig {
+          $($name: read_env(stringify!($name), env, errors),)*
+        }
+      }
+
+      fn finish(self, profile: Profile, errors: &mut Vec<String>) -> Option<Config> {
+        $(if self.$name.is_none() {
+          errors.push(format!("{} is not set (env {} or `{}` in the config file)", stringify!($name), env_name(stringify!($name)), stringify!($name)));
+        })*
+        Some(Config {
+          profile,
+          $($name: self.$name?,)*
+        })
+      }
+    }
+  };
+}
+
+config! {
+  listen_addr: SocketAddr,
+  metrics_listen_addr: SocketAddr, // Serves /metrics, keep this port off the public load balancer
+  app_base_url: String,
+  database_url: Secret,
+  database_max_connections: u32,
+  redis_url: Secret,
+  resend_api_key: Secret,
+  email_from: String,
+  jwt_keys_dir: String,
+  jwt_active_kid: String,
+  jwt_secret: Secret,
+  totp_encryption_key: Secret,
+  google_console_client_id: String,
+  captcha_secret_key: Secret,
+  vonage_api_key: Secret,
+  vonage_api_secret: Secret,
+  company_phone: String,
+  breached_passwords_file: String, // Empty turns the breached password check off
+  password_min_length: usize,
+  password_min_strength: u8,
+  access_token_expiration_sec: i64,
+  refresh_token_expiration_days: u64,
+  max_sign_in_attempts: u32,
+  sign_in_attempts_lock_sec: i64,
+  sign_up_session_expiration_sec: u64,
+  sms_code_expiration_sec: u64,
+  sms_code_resend_sec: i64,
+  sms_code_max_attempts: u32,
+  email_verification_expiration_sec: u64,
+  email_verification_resend_sec: u64,
+  password_reset_expiration_sec: u64,
+  password_reset_resend_sec: u64,
+  email_change_expiration_sec: u64,
+  email_change_resend_sec: u64,
+  magic_link_expiration_sec: u64,
+  magic_link_resend_sec: u64,
+  picture_allowed_hosts: Vec<String>,
+  phone_default_country: String,
+  phone_allowed_countries: Vec<String>,
+  reauth_max_age_sec: i64,
+  account_deletion_grace_days: i64,
+  sign_in_challenge_expiration_sec: u64,
+  sign_in_challenge_max_attempts: u32,
+  second_factor_max_failures: u32, // Across all challenges, a new password sign in doesn't reset it
+  second_factor_lock_sec: i64,
+  webauthn_challenge_expiration_sec: u64,
+  health_check_timeout_ms: u64,
+  log_format: String, // text or json
+  log_reveal_pii: bool, // Prints emails, phones, tokens and codes unmasked, dev profile only
+}
+
+// Secrets and per-deployment values (urls, keys, email_from...) have no default on purpose
+fn defaults(profile: Profile) -> RawConfig {
+  RawConfig {
+    listen_addr: Some(SocketAddr::from(([0, 0, 0, 0], 3000))),
+    metrics_listen_addr: Some(SocketAddr::from(([0, 0, 0, 0], 9100))),
+    database_max_connections: Some(match profile {
+      Profile::Dev => 5,
+      Profile::Staging => 10,
+      Profile::Prod => 20,
+    }),
+    company_phone: Some("972585339500".to_string()),
+    breached_passwords_file: Some(String::new()),
+    password_min_length: Some(8),
+    password_min_strength: Some(2),
+    access_token_expiration_sec: Some(900),
+    refresh_token_expiration_days: Some(30),
+    max_sign_in_attempts: Some(10),
+    sign_in_attempts_lock_sec: Some(300),
+    sign_up_session_expiration_sec: Some(900),
+    sms_code_expiration_sec: Some(300),
+    sms_code_resend_sec: Some(180),
+    sms_code_max_attempts: Some(5),
+    email_verification_expiration_sec: Some(86400),
+    email_verification_resend_sec: Some(60),
+    password_reset_expiration_sec: Some(1800),
+    password_reset_resend_sec: Some(60),
+    email_change_expiration_sec: Some(86400),
+    email_change_resend_sec: Some(60),
+    magic_link_expiration_sec: Some(900),
+    magic_link_resend_sec: Some(60),
+    picture_allowed_hosts: Some(vec!["lh3.googleusercontent.com".to_string()]),
+    phone_default_country: Some("IL".to_string()),
+    phone_allowed_countries: Some(vec!["IL".to_string()]),
+    reauth_max_age_sec: Some(300),
+    account_deletion_grace_days: Some(30),
+    sign_in_challenge_expiration_sec: Some(300),
+    sign_in_challenge_max_attempts: Some(5),
+    second_factor_max_failures: Some(10),
+    second_factor_lock_sec: Some(3600),
+    webauthn_challenge_expiration_sec: Some(300),
+    health_check_timeout_ms: Some(2000),
+    log_format: Some("text".to_string()),
+    log_reveal_pii: Some(false),
+    ..RawConfig::default()
+  }
+}
+
+/*** Loading ***/
+
+fn load_file(profile: Profile, env: Env, read_file: impl FnOnce(&str) -> io::Result<String>, errors: &mut Vec<String>) -> RawConfig {
+  let (path, required) = match env("CONFIG_FILE") {
+    Some(path) => (path, true),
+    None => ("config.toml".to_string(), false),
+  };
+  let contents = match read_file(&path) {
+    Ok(contents) => contents,
+    Err(error) => {
+      if required {
+        errors.push(format!("{path}: {error}"));
+      }
+      return RawConfig::default();
+    },
+  };
+  let mut table: Table = match contents.parse() {
+    Ok(table) => table,
+    Err(error) => {
+      errors.push(format!("{path}: {}", error.message()));
+      return RawConfig::default();
+    },
+  };
+
+  let profiles = match table.remove("profile") {
+    Some(Value::Table(profiles)) => profiles,
+    Some(_) => {
+      errors.push(format!("{path}: profile must be a table of [profile.<name>] sections"));
+      Table::new()
+    },
+    None => Table::new(),
+  };
+  check_unknown_keys(&table, &path, errors);
+
+  let mut raw = RawConfig::from_table(&table, &path, errors);
+  for (name, section) in &profiles {
+    let source = format!("{path} [profile.{name}]");
+    let Some(section_profile) = Profile::parse(name) else {
+      errors.push(format!("{source}: unknown profile, expected dev, staging or prod"));
+      continue;
+    };
+    let Value::Table(section) = section else {
+      errors.push(format!("{source}: must be a table"));
+      continue;
+    };
+    check_unknown_keys(section, &source, errors);
+    // Other profiles are still parsed, so a typo there is caught before it's deployed
+    let section = RawConfig::from_table(section, &source, errors);
+    if section_profile == profile {
+      raw.merge(section);
+    }
+  }
+  raw
+}
+
+fn check_unknown_keys(table: &Table, source: &str, errors: &mut Vec<String>) {
+  for key in table.keys() {
+    if !RawConfig::KEYS.contains(&key.as_str()) {
+      errors.push(format!("{source}: unknown key `{key}`"));
+    }
+  }
+}
+
+impl Config {
+  pub fn load() -> Result<Config, ConfigErrors> {
+    Config::load_from(&|name| var(name).ok(), |path| fs::read_to_string(path))
+  }
+
+  fn load_from(env: Env, read_file: impl FnOnce(&str) -> io::Result<String>) -> Result<Config, ConfigErrors> {
+    let mut errors = Vec::new();
+    let profile = match env("APP_PROFILE") {
+      Some(profile) => Profile::parse(&profile).unwrap_or_else(|| {
+        errors.push(format!("env APP_PROFILE: unknown profile `{profile}`, expected dev, staging or prod"));
+        Profile::Dev
+      }),
+      None => Profile::Dev,
+    };
+
+    let mut raw = defaults(profile);
+    raw.merge(load_file(profile, env, read_file, &mut errors));
+    raw.merge(RawConfig::from_env(env, &mut errors));
+
+    // Before finish, which gives up on the first missing key, so bad values are reported along with missing ones
+    raw.validate(profile, &mut errors);
+    match raw.finish(profile, &mut errors) {
+      Some(config) if errors.is_empty() => Ok(config),
+      _ => Err(ConfigErrors(errors)),
+    }
+  }
+}
+
+impl RawConfig {
+  // Only checks the keys that are set, finish reports the rest
+  fn validate(&self, profile: Profile, errors: &mut Vec<String>) {
+    let positive = [
+      ("database_max_connections", self.database_max_connections.map(i64::from)),
+      ("password_min_length", self.password_min_length.map(|value| value as i64)),
+      ("access_token_expiration_sec", self.access_token_expiration_sec),
+      ("refresh_token_expiration_days", self.refresh_token_expiration_days.map(|value| value as i64)),
+      ("max_sign_in_attempts", self.max_sign_in_attempts.map(i64::from)),
+      ("sign_in_attempts_lock_sec", self.sign_in_attempts_lock_sec),
+      ("sign_up_session_expiration_sec", self.sign_up_session_expiration_sec.map(|value| value as i64)),
+      ("sms_code_expiration_sec", self.sms_code_expiration_sec.map(|value| value as i64)),
+      ("sms_code_resend_sec", self.sms_code_resend_sec),
+      ("sms_code_max_attempts", self.sms_code_max_attempts.map(i64::from)),
+      ("email_verification_expiration_sec", self.email_verification_expiration_sec.map(|value| value as i64)),
+      ("email_verification_resend_sec", self.email_verification_resend_sec.map(|value| value as i64)),
+      ("password_reset_expiration_sec", self.password_reset_expiration_sec.map(|value| value as i64)),
+      ("password_reset_resend_sec", self.password_reset_resend_sec.map(|value| value as i64)),
+      ("email_change_expiration_sec", self.email_change_expiration_sec.map(|value| value as i64)),
+      ("email_change_resend_sec", self.email_change_resend_sec.map(|value| value as i64)),
+      ("magic_link_expiration_sec", self.magic_link_expiration_sec.map(|value| value as i64)),
+      ("magic_link_resend_sec", self.magic_link_resend_sec.map(|value| value as i64)),
+      ("reauth_max_age_sec", self.reauth_max_age_sec),
+      ("account_deletion_grace_days", self.account_deletion_grace_days),
+      ("sign_in_challenge_expiration_sec", self.sign_in_challenge_expiration_sec.map(|value| value as i64)),
+      ("sign_in_challenge_max_attempts", self.sign_in_challenge_max_attempts.map(i64::from)),
+      ("second_factor_max_failures", self.second_factor_max_failures.map(i64::from)),
+      ("second_factor_lock_sec", self.second_factor_lock_sec),
+      ("webauthn_challenge_expiration_sec", self.webauthn_challenge_expiration_sec.map(|value| value as i64)),
+      ("health_check_timeout_ms", self.health_check_timeout_ms.map(|value| value as i64)),
+    ];
+    for (key, value) in positive {
+      if value.is_some_and(|value| value <= 0) {
+        errors.push(format!("{key} must be greater than 0"));
+      }
+    }
+
+    if self.metrics_listen_addr.is_some() && self.metrics_listen_addr == self.listen_addr {
+      errors.push("metrics_listen_addr must differ from listen_addr, /metrics isn't served on the public port".to_string());
+    }
+    if let Some(app_base_url) = &self.app_base_url {
+      match reqwest::Url::parse(app_base_url) {
+        Ok(url) if url.scheme() == "https" => {},
+        // Passkeys and secure cookies need https anywhere real users are
+        Ok(url) if url.scheme() == "http" && profile == Profile::Dev => {},
+        Ok(_) => errors.push(format!("app_base_url must use https in the {} profile", profile.as_str())),
+        Err(error) => errors.push(format!("app_base_url: {error}")),
+      }
+    }
+    if let Some(log_format) = &self.log_format && !matches!(log_format.as_str(), "text" | "json") {
+      errors.push(format!("log_format must be text or json, got `{log_format}`"));
+    }
+    if self.log_reveal_pii == Some(true) && profile != Profile::Dev {
+      errors.push(format!("log_reveal_pii can only be turned on in the dev profile, not {}", profile.as_str()));
+    }
+    if self.company_phone.as_ref().is_some_and(String::is_empty) {
+      errors.push("company_phone must be set, it's the sender of every SMS".to_string());
+    }
+    if self.email_from.as_ref().is_some_and(|email_from| !email_from.contains('@')) {
+      errors.push("email_from must be an email address".to_string());
+    }
+    if let Some(key) = &self.totp_encryption_key && (key.expose().len() != 64 || hex::decode(key.expose()).is_err()) {
+      errors.push("totp_encryption_key must be 32 bytes of hex, generate one with `openssl rand -hex 32`".to_string());
+    }
+    if self.password_min_length.is_some_and(|value| value > 128) {
+      errors.push("password_min_length can't be more than 128, the longest password allowed".to_string());
+    }
+    if self.password_min_strength.is_some_and(|value| value > 4) {
+      errors.push("password_min_strength must be between 0 and 4".to_string());
+    }
+    if self.phone_allowed_countries.as_ref().is_some_and(Vec::is_empty) {
+      errors.push("phone_allowed_countries must list at least one country".to_string());
+    }
+    for country in self.phone_allowed_countries.iter().flatten().chain(&self.phone_default_country) {
+      if country.len() != 2 || !country.chars().all(|c| c.is_ascii_uppercase()) {
+        errors.push(format!("`{country}` is not an ISO 3166-1 alpha-2 country code, e.g. IL"));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  // Every key without a default, so a load only fails on what the test sets
+  const REQUIRED: &[(&str, &str)] = &[
+    ("APP_BASE_URL", "https://app.example.com"),
+    ("DATABASE_URL", "postgres://localhost/app"),
+    ("REDIS_URL", "redis://localhost"),
+    ("RESEND_API_KEY", "re_test"),
+    ("EMAIL_FROM", "no-reply@example.com"),
+    ("JWT_KEYS_DIR", "keys"),
+    ("JWT_ACTIVE_KID", "kid-1"),
+    ("JWT_SECRET", "secret"),
+    ("TOTP_ENCRYPTION_KEY", "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff"),
+    ("GOOGLE_CONSOLE_CLIENT_ID", "client-id"),
+    ("CAPTCHA_SECRET_KEY", "captcha"),
+    ("VONAGE_API_KEY", "vonage-key"),
+    ("VONAGE_API_SECRET", "vonage-secret"),
+  ];
+
+  fn load_with(env: &[(&str, &str)], file: Option<&str>) -> Result<Config, ConfigErrors> {
+    let env: HashMap<String, String> = env.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    Config::load_from(&|name| env.get(name).cloned(), |_| match file {
+      Some(contents) => Ok(contents.to_string()),
+      None => Err(io::Error::from(io::ErrorKind::NotFound)),
+    })
+  }
+
+  // The required keys plus `env`, which wins on the same name
+  fn load(env: &[(&str, &str)], file: Option<&str>) -> Result<Config, ConfigErrors> {
+    let env: Vec<(&str, &str)> = REQUIRED.iter().chain(env).copied().collect();
+    load_with(&env, file)
+  }
+
+  fn errors(result: Result<Config, ConfigErrors>) -> Vec<String> {
+    result.expect_err("config should not load").0
+  }
+
+  fn has_error(errors: &[String], text: &str) -> bool {
+    errors.iter().any(|error| error.contains(text))
+  }
+
+  #[test]
+  fn defaults_depend_on_the_profile() {
+    let config = load(&[], None).unwrap();
+    assert_eq!(config.profile, Profile::Dev);
+    assert_eq!(config.database_max_connections, 5);
+    assert_eq!(config.sms_code_resend_sec, 180);
+
+    let config = load(&[("APP_PROFILE", "prod")], None).unwrap();
+    assert_eq!(config.profile, Profile::Prod);
+    assert_eq!(config.database_max_connections, 20);
+  }
+
+  #[test]
+  fn file_then_profile_section_then_env_override() {
+    let top_level = "sms_code_resend_sec = 100";
+    assert_eq!(load(&[], Some(top_level)).unwrap().sms_code_resend_sec, 100);
+
+    let with_profile = "sms_code_resend_sec = 100\n[profile.dev]\nsms_code_resend_sec = 50";
+    assert_eq!(load(&[], Some(with_profile)).unwrap().sms_code_resend_sec, 50);
+
+    let config = load(&[("SMS_CODE_RESEND_SEC", "20")], Some(with_profile)).unwrap();
+    assert_eq!(config.sms_code_resend_sec, 20);
+  }
+
+  #[test]
+  fn other_profile_sections_are_not_applied() {
+    let file = "sms_code_resend_sec = 100\n[profile.prod]\nsms_code_resend_sec = 50";
+    assert_eq!(load(&[], Some(file)).unwrap().sms_code_resend_sec, 100);
+    assert_eq!(load(&[("APP_PROFILE", "prod")], Some(file)).unwrap().sms_code_resend_sec, 50);
+  }
+
+  #[test]
+  fn env_lists_are_comma_separated() {
+    let config = load(&[("PHONE_ALLOWED_COUNTRIES", "IL, US,")], None).unwrap();
+    assert_eq!(config.phone_allowed_countries, vec!["IL", "US"]);
+  }
+
+  #[test]
+  fn unknown_keys_are_reported() {
+    let file = "sms_code_resnd_sec = 100\n[profile.prod]\nlog_fromat = \"json\"";
+    let errors = errors(load(&[], Some(file)));
+    assert!(has_error(&errors, "config.toml: unknown key `sms_code_resnd_sec`"));
+    // Caught even though the active profile is dev
+    assert!(has_error(&errors, "config.toml [profile.prod]: unknown key `log_fromat`"));
+  }
+
+  #[test]
+  fn unknown_profiles_are_reported() {
+    assert!(has_error(&errors(load(&[("APP_PROFILE", "qa")], None)), "env APP_PROFILE: unknown profile `qa`"));
+    assert!(has_error(&errors(load(&[], Some("[profile.qa]\nlog_format = \"json\""))), "[profile.qa]: unknown profile"));
+    assert!(has_error(&errors(load(&[], Some("profile = 1"))), "profile must be a table"));
+    assert!(has_error(&errors(load(&[], Some("[profile]\ndev = 1"))), "[profile.dev]: must be a table"));
+  }
+
+  #[test]
+  fn config_file_is_only_required_when_set() {
+    assert!(load(&[], None).is_ok());
+    assert!(has_error(&errors(load(&[("CONFIG_FILE", "app.toml")], None)), "app.toml:"));
+  }
+
+  #[test]
+  fn bad_values_are_reported_with_missing_keys() {
+    let errors = errors(load_with(&[("LOG_FORMAT", "xml"), ("SMS_CODE_RESEND_SEC", "0"), ("MAX_SIGN_IN_ATTEMPTS", "ten")], None));
+    assert!(has_error(&errors, "database_url is not set"));
+    assert!(has_error(&errors, "log_format must be text or json, got `xml`"));
+    assert!(has_error(&errors, "sms_code_resend_sec must be greater than 0"));
+    assert!(has_error(&errors, "env MAX_SIGN_IN_ATTEMPTS: invalid digit"));
+  }
+
+  #[test]
+  fn metrics_need_their_own_address() {
+    let errors = errors(load(&[("METRICS_LISTEN_ADDR", "0.0.0.0:3000")], None));
+    assert!(has_error(&errors, "metrics_listen_addr must differ from listen_addr"));
+  }
+
+  #[test]
+  fn prod_rejects_http_and_revealed_pii() {
+    let errors = errors(load(&[("APP_PROFILE", "prod"), ("APP_BASE_URL", "http://app.example.com"), ("LOG_REVEAL_PII", "true")], None));
+    assert!(has_error(&errors, "app_base_url must use https in the prod profile"));
+    assert!(has_error(&errors, "log_reveal_pii can only be turned on in the dev profile, not prod"));
+
+    assert!(load(&[("APP_BASE_URL", "http://localhost:8080"), ("LOG_REVEAL_PII", "true")], None).is_ok());
+  }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::Serialize;
+
+use crate::app_state::AppState;
+
+/*** Json Structs **/
+
+#[derive(Serialize)]
+pub struct HealthResponse {
+  status: HealthStatus,
+  checks: BTreeMap<&'static str, CheckResult>,
+}
+
+#[derive(Serialize)]
+pub struct CheckResult {
+  status: HealthStatus,
+  critical: bool, // Only critical checks can fail readiness
+  latency_ms: Option<u64>,
+  error: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+  Ok,
+  Degraded,
+  Unavailable,
+}
+
+/*** Helpers ***/
+
+async fn timed_check<F>(timeout: Duration, check: F) -> CheckResult
+where
+  F: Future<Output = Result<(), anyhow::Error>>,
+{
+  let started = Instant::now();
+  let result = tokio::time::timeout(timeout, check).await;
+  let latency_ms = Some(started.elapsed().as_millis() as u64);
+  let error = match result {
+    Ok(Ok(())) => None,
+    Ok(Err(_)) => Some("check_failed"),
+    Err(_) => Some("timed_out"),
+  };
+
+  CheckResult {
+    status: if error.is_none() { HealthStatus::Ok } else { HealthStatus::Unavailable },
+    critical: true,
+    latency_ms,
+    error,
+  }
+}
+
+async fn check_postgres(app_state: &AppState) -> Result<(), anyhow::Error> {
+  sqlx::query("SELECT 1").execute(&app_state.pool).await?;
+  Ok(())
+}
+
+async fn check_redis(app_state: &AppState) -> Result<(), anyhow::Error> {
+  let mut conn = app_state.redis_pool.get().await?;
+  let _: String = deadpool_redis::redis::cmd("PING").query_async(&mut conn).await?;
+  Ok(())
+}
+
+// Providers aren't called, that would cost money and rate limit on every probe,
+// this only reports whether the service was given what it needs to reach them
+fn check_provider_config(configured: bool) -> CheckResult {
+  CheckResult {
+    status: if configured { HealthStatus::Ok } else { HealthStatus::Degraded },
+    critical: false,
+    latency_ms: None,
+    error: if configured { None } else { Some("not_configured") },
+  }
+}
+
+/*** Handlers ***/
+
+// The process is up and serving requests, dependencies aren't looked at
+pub async fn handle_live() -> (StatusCode, Json<HealthResponse>) {
+  (StatusCode::OK, Json(HealthResponse {
+    status: HealthStatus::Ok,
+    checks: BTreeMap::new(),
+  }))
+}
+
+// 503 when a critical dependency is down, so the orchestrator stops routing traffic here
+pub async fn handle_ready(State(app_state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+  let timeout = Duration::from_millis(app_state.health_check_timeout_ms);
+  let (postgres, redis) = tokio::join!(
+    timed_check(timeout, check_postgres(&app_state)),
+    timed_check(timeout, check_redis(&app_state)),
+  );
+
+  let mut checks = BTreeMap::new();
+  checks.insert("postgres", postgres);
+  checks.insert("redis", redis);
+  checks.insert("email", check_provider_config(!app_state.email_from.is_empty()));
+  checks.insert("sms", check_provider_config(!app_state.vonage_api_key.is_empty() && !app_state.vonage_api_secret.is_empty()));
+  checks.insert("google", check_provider_config(!app_state.google_console_client_id.is_empty()));
+  checks.insert("captcha", check_provider_config(!app_state.captcha_secret_key.is_empty()));
+
+  let status = if checks.values().any(|check| check.critical && check.status != HealthStatus::Ok) {
+    HealthStatus::Unavailable
+  } else if checks.values().any(|check| check.status != HealthStatus::Ok) {
+    HealthStatus::Degraded
+  } else {
+    HealthStatus::Ok
+  };
+
+  if status == HealthStatus::Unavailable {
+    tracing::error!(
+      event = "health_ready_failure",
+      failed = ?(checks.iter().filter(|(_, check)| check.status == HealthStatus::Unavailable).map(|(name, _)| *name).collect::<Vec<_>>()),
+    );
+  }
+  let status_code = if status == HealthStatus::Unavailable { StatusCode::SERVICE_UNAVAILABLE } else { StatusCode::OK };
+  (status_code, Json(HealthResponse {
+    status,
+    checks,
+  }))
+}
@@ -1,10 +1,19 @@
 mod auth;
 mod api;
 mod app_state;
+mod config;
 mod ping;
+mod health;
+mod telemetry;
+mod request_id;
+mod redact;
+mod admin;
+mod users;
 
 use axum::{
-    routing::{get, post},
+    http::HeaderName,
+    routing::{get, post, patch, delete},
+    middleware,
     Router,
 };
 use std::net::SocketAddr;
@@ -16,41 +25,133 @@ use crate::auth::sign_in;
 use crate::auth::sign_up_start;
 use crate::auth::sign_up_sms;
 use crate::auth::sign_up_complete;
+use crate::auth::verify_email;
+use crate::auth::password_reset;
+use crate::auth::change_password;
+use crate::auth::sign_in_methods;
+use crate::auth::change_email;
+use crate::auth::two_factor;
+use crate::auth::passkeys;
+use crate::auth::sms_sign_in;
+use crate::auth::magic_link;
+use crate::auth::change_phone;
+use crate::users::{profile, deletion, export};
+use crate::auth::refresh;
+use crate::auth::logout;
+use crate::auth::sessions;
+use crate::auth::jwt_keys;
 use crate::app_state::create_app_state;
+use crate::config::Config;
 
 #[tokio::main]
 async fn main() {
   // Load .env
   dotenv().ok();
 
+  // Loaded before logging is set up since it picks the log format, errors go straight to stderr
+  let config = match Config::load() {
+    Ok(config) => config,
+    Err(errors) => {
+      eprintln!("{errors}");
+      std::process::exit(1);
+    },
+  };
+
+  // json puts the request span fields (request_id, route, user_uuid) on every line for log ingestion
+  let (text_layer, json_layer) = match config.log_format.as_str() {
+    "json" => (None, Some(fmt::layer().json().with_current_span(true).with_span_list(false))),
+    _ => (Some(fmt::layer()), None),
+  };
   tracing_subscriber::registry()
-    .with(fmt::layer())
+    .with(text_layer)
+    .with(json_layer)
     .with(EnvFilter::from_default_env())
     .init();
+  redact::set_reveal(config.log_reveal_pii);
+  if config.log_reveal_pii {
+    tracing::warn!(event = "log_reveal_pii_enabled", "Emails, phone numbers, tokens and codes are logged unmasked");
+  }
+  // Secrets print as [redacted]
+  tracing::info!(
+    event = "config_loaded",
+    profile = config.profile.as_str(),
+    config = ?config,
+  );
 
-  let app_state = create_app_state().await;
+  let app_state = create_app_state(&config).await;
+  tokio::spawn(deletion::run_purge_task(app_state.clone()));
+  tokio::spawn(telemetry::run_upkeep_task(app_state.metrics.clone()));
 
   // Allow all origins for dev
   let cors = CorsLayer::new()
     .allow_origin(Any)
     .allow_methods(Any)
-    .allow_headers(Any);
+    .allow_headers(Any)
+    .expose_headers([HeaderName::from_static(request_id::REQUEST_ID_HEADER)]);
+
+  // Own listener so scrapes never go through the public port
+  let metrics_app = Router::new()
+    .route("/metrics", get(telemetry::handle_metrics))
+    .with_state(app_state.clone());
 
   let app = Router::new()
     .route("/auth/sign-in", post(sign_in::handle_sign_in))
+    .route("/auth/sign-in/totp", post(two_factor::handle_totp_sign_in))
+    .route("/auth/sign-in/webauthn/start", post(passkeys::handle_sign_in_start))
+    .route("/auth/sign-in/sms/send", post(sms_sign_in::handle_send_code))
+    .route("/auth/sign-in/magic-link/send", post(magic_link::handle_send_link))
     .route("/auth/me", get(sign_in::handle_jwt_sign_in))
+    .route("/auth/refresh", post(refresh::handle_refresh))
+    .route("/auth/logout", post(logout::handle_logout))
+    .route("/auth/logout-all", post(logout::handle_logout_all))
+    .route("/auth/sessions", get(sessions::handle_list_sessions))
+    .route("/auth/sessions/{id}", delete(sessions::handle_revoke_session))
     .route("/ping", get(ping::ping_handler))
+    .route("/health/live", get(health::handle_live))
+    .route("/health/ready", get(health::handle_ready))
+    .route("/.well-known/jwks.json", get(jwt_keys::handle_jwks))
     .route("/auth/sign-up/start", post(sign_up_start::handle_start))
     .route("/auth/sign-up/send-sms", post(sign_up_sms::handle_sms_request))
     .route("/auth/sign-up/verify-sms", post(sign_up_sms::handle_sms_verify))
     .route("/auth/sign-up/complete", post(sign_up_complete::handle_complete))
+    .route("/auth/verify-email", post(verify_email::handle_verify_email))
+    .route("/auth/verify-email/resend", post(verify_email::handle_resend))
+    .route("/auth/password/forgot", post(password_reset::handle_forgot))
+    .route("/auth/password/reset", post(password_reset::handle_reset))
+    .route("/auth/password/change", post(change_password::handle_change_password))
+    .route("/auth/password/link", post(sign_in_methods::handle_link_password))
+    .route("/auth/google/unlink", post(sign_in_methods::handle_unlink_google))
+    .route("/auth/email/change", post(change_email::handle_request_change))
+    .route("/auth/email/change/confirm", post(change_email::handle_confirm_change))
+    .route("/auth/email/change/cancel", post(change_email::handle_cancel_change))
+    .route("/auth/phone/change", post(change_phone::handle_request_change))
+    .route("/auth/phone/change/verify", post(change_phone::handle_verify_change))
+    .route("/auth/totp/setup", post(two_factor::handle_totp_setup))
+    .route("/auth/totp/enable", post(two_factor::handle_totp_enable))
+    .route("/auth/totp/disable", post(two_factor::handle_totp_disable))
+    .route("/auth/passkeys", get(passkeys::handle_list_passkeys))
+    .route("/auth/passkeys/register/start", post(passkeys::handle_register_start))
+    .route("/auth/passkeys/register/finish", post(passkeys::handle_register_finish))
+    .route("/auth/passkeys/{id}", patch(passkeys::handle_rename_passkey).delete(passkeys::handle_delete_passkey))
+    .route("/users/me", patch(profile::handle_update_profile).delete(deletion::handle_delete_account))
+    .route("/users/me/export", get(export::handle_export))
+    .route("/admin/users/{uuid}/roles", get(admin::handle_get_roles).post(admin::handle_grant_role))
+    .route("/admin/users/{uuid}/roles/{role}", delete(admin::handle_revoke_role))
+    .route_layer(middleware::from_fn(telemetry::track_requests))
+    .route_layer(middleware::from_fn(request_id::record_route))
+    .layer(middleware::from_fn(request_id::propagate))
     .layer(cors)
     .with_state(app_state);
 
-  let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+  let metrics_addr = config.metrics_listen_addr;
+  let metrics_listener = tokio::net::TcpListener::bind(metrics_addr).await.unwrap();
+  println!("Serving metrics on http://{}/metrics", metrics_addr);
+  tokio::spawn(async move { axum::serve(metrics_listener, metrics_app).await.unwrap() });
+
+  let addr = config.listen_addr;
   println!("Listening on http://{}", addr);
 
-  axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app.into_make_service())
+  axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app.into_make_service_with_connect_info::<SocketAddr>())
     .await.unwrap();
 }
 
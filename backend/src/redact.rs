@@ -0,0 +1,177 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use sha2::{Digest, Sha256};
+
+// Wrappers for sensitive values in tracing fields, e.g. `email = %Email(&user.email)`.
+// They print a masked or hashed form unless revealing was switched on with log_reveal_pii,
+// which the config only allows in the dev profile.
+
+static REVEAL: AtomicBool = AtomicBool::new(false);
+
+pub fn set_reveal(reveal: bool) {
+  REVEAL.store(reveal, Ordering::Relaxed);
+}
+
+fn reveal() -> bool {
+  REVEAL.load(Ordering::Relaxed)
+}
+
+// Same input gives the same output, so a value can still be followed across log lines
+fn short_hash(value: &str) -> String {
+  hex::encode(&Sha256::digest(value.as_bytes())[..4])
+}
+
+macro_rules! debug_as_display {
+  ($($ty:ident),*) => {
+    $(impl fmt::Debug for $ty<'_> {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+      }
+    })*
+  };
+}
+
+// Bearer, refresh, Google id and link tokens: j***@gmail.com style masking would still leak them
+pub struct Token<'a>(pub &'a str);
+
+impl fmt::Display for Token<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if reveal() {
+      return f.write_str(self.0);
+    }
+    write!(f, "token:{}", short_hash(self.0))
+  }
+}
+
+// Keeps the first letter and the domain, e.g. j***@gmail.com
+pub struct Email<'a>(pub &'a str);
+
+impl fmt::Display for Email<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if reveal() {
+      return f.write_str(self.0);
+    }
+    match self.0.split_once('@') {
+      Some((local, domain)) => {
+        let first = local.chars().next().map(String::from).unwrap_or_default();
+        write!(f, "{first}***@{domain}")
+      },
+      None => write!(f, "email:{}", short_hash(self.0)),
+    }
+  }
+}
+
+// Keeps the country code side and the last two digits, e.g. +9725*****89
+pub struct Phone<'a>(pub &'a str);
+
+impl fmt::Display for Phone<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if reveal() {
+      return f.write_str(self.0);
+    }
+    let chars: Vec<char> = self.0.chars().collect();
+    if chars.len() < 8 {
+      return f.write_str("***");
+    }
+    let head: String = chars[..5].iter().collect();
+    let tail: String = chars[chars.len() - 2..].iter().collect();
+    write!(f, "{head}{}{tail}", "*".repeat(chars.len() - 7))
+  }
+}
+
+// One-time codes (SMS, TOTP, recovery, confirmation), nothing about them is worth logging
+pub struct Code<'a>(pub &'a str);
+
+impl fmt::Display for Code<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if reveal() {
+      return f.write_str(self.0);
+    }
+    f.write_str("******")
+  }
+}
+
+debug_as_display!(Token, Email, Phone, Code);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::{Mutex, MutexGuard};
+
+  // REVEAL is global and tests run in parallel, every test holds this while it formats
+  static REVEAL_LOCK: Mutex<()> = Mutex::new(());
+
+  fn masked() -> MutexGuard<'static, ()> {
+    let guard = REVEAL_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    set_reveal(false);
+    guard
+  }
+
+  #[test]
+  fn tokens_are_hashed() {
+    let _guard = masked();
+    let token = Token("eyJhbGciOiJSUzI1NiJ9.payload.signature").to_string();
+    assert!(token.starts_with("token:"));
+    assert_eq!(token.len(), "token:".len() + 8);
+    assert!(token["token:".len()..].chars().all(|c| c.is_ascii_hexdigit()));
+    // Stable, so the same token can be followed across lines
+    assert_eq!(token, Token("eyJhbGciOiJSUzI1NiJ9.payload.signature").to_string());
+    assert_ne!(token, Token("another").to_string());
+  }
+
+  #[test]
+  fn emails_keep_the_first_letter_and_domain() {
+    let _guard = masked();
+    assert_eq!(Email("dana.cohen@gmail.com").to_string(), "d***@gmail.com");
+    assert_eq!(Email("@gmail.com").to_string(), "***@gmail.com");
+  }
+
+  #[test]
+  fn emails_without_at_are_hashed() {
+    let _guard = masked();
+    let email = Email("dana.cohen").to_string();
+    assert_eq!(email, format!("email:{}", short_hash("dana.cohen")));
+    assert!(!email.contains("dana"));
+  }
+
+  #[test]
+  fn phones_keep_the_country_side_and_last_digits() {
+    let _guard = masked();
+    assert_eq!(Phone("+972522345678").to_string(), "+9725******78");
+    assert_eq!(Phone("12345678").to_string(), "12345*78");
+  }
+
+  #[test]
+  fn short_phones_are_fully_masked() {
+    let _guard = masked();
+    assert_eq!(Phone("1234567").to_string(), "***");
+    assert_eq!(Phone("").to_string(), "***");
+  }
+
+  #[test]
+  fn codes_are_never_shown() {
+    let _guard = masked();
+    assert_eq!(Code("123456").to_string(), "******");
+    assert_eq!(Code("ABCD-EFGH-1234").to_string(), "******");
+  }
+
+  #[test]
+  fn debug_matches_display() {
+    let _guard = masked();
+    assert_eq!(format!("{:?}", Email("dana@gmail.com")), "d***@gmail.com");
+    assert_eq!(format!("{:?}", Code("123456")), "******");
+  }
+
+  #[test]
+  fn reveal_prints_the_raw_values() {
+    let _guard = masked();
+    set_reveal(true);
+    assert_eq!(Token("secret-token").to_string(), "secret-token");
+    assert_eq!(Email("dana@gmail.com").to_string(), "dana@gmail.com");
+    assert_eq!(Phone("+972522345678").to_string(), "+972522345678");
+    assert_eq!(Code("123456").to_string(), "123456");
+
+    set_reveal(false);
+    assert_eq!(Code("123456").to_string(), "******");
+  }
+}
@@ -0,0 +1,149 @@
+use std::future::Future;
+use axum::{
+  body::{to_bytes, Body},
+  extract::{MatchedPath, Request},
+  http::{header, HeaderValue},
+  middleware::Next,
+  response::Response,
+};
+use serde_json::Value;
+use tracing::{field, Instrument, Span};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// Error bodies are small json objects, anything bigger is passed through untouched
+const MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+tokio::task_local! {
+  static REQUEST_ID: String;
+}
+
+/*** Helpers ***/
+
+// Client supplied ids end up in logs and outbound headers, so only plain ones are kept
+fn is_valid(id: &str) -> bool {
+  !id.is_empty() && id.len() <= 128 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Id of the request being handled, None outside of a request (background tasks, startup)
+pub fn current() -> Option<String> {
+  REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+// tokio::spawn for work started by a request, keeps its id and span so the logs still line up
+pub fn spawn<F>(future: F)
+where
+  F: Future<Output = ()> + Send + 'static,
+{
+  let future = future.in_current_span();
+  match current() {
+    Some(id) => tokio::spawn(REQUEST_ID.scope(id, future)),
+    None => tokio::spawn(future),
+  };
+}
+
+// Called by the auth extractors, so every log line after authentication carries the user
+pub fn record_user(user_uuid: &Uuid) {
+  Span::current().record("user_uuid", field::display(user_uuid));
+}
+
+async fn add_to_response(response: Response, id: &str) -> Response {
+  let (mut parts, body) = response.into_parts();
+  if let Ok(value) = HeaderValue::from_str(id) {
+    parts.headers.insert(REQUEST_ID_HEADER, value);
+  }
+
+  let is_json = parts.headers.get(header::CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.starts_with("application/json"));
+  if !is_json || !(parts.status.is_client_error() || parts.status.is_server_error()) {
+    return Response::from_parts(parts, body);
+  }
+
+  let Ok(bytes) = to_bytes(body, MAX_ERROR_BODY_BYTES).await else {
+    return Response::from_parts(parts, Body::empty());
+  };
+  match serde_json::from_slice::<Value>(&bytes) {
+    Ok(Value::Object(mut error)) => {
+      error.insert("request_id".to_string(), Value::String(id.to_string()));
+      parts.headers.remove(header::CONTENT_LENGTH);
+      Response::from_parts(parts, Body::from(Value::Object(error).to_string()))
+    },
+    _ => Response::from_parts(parts, Body::from(bytes)),
+  }
+}
+
+/*** Middleware ***/
+
+// Outer layer so fallback 404 and 405 responses get an id too. Keeps the client's X-Request-Id
+// or makes one up, opens the request span and sends the id back in the response header and in
+// every json error body
+pub async fn propagate(request: Request, next: Next) -> Response {
+  let id = request.headers().get(REQUEST_ID_HEADER)
+    .and_then(|value| value.to_str().ok())
+    .filter(|id| is_valid(id))
+    .map(String::from)
+    .unwrap_or_else(|| Uuid::new_v4().to_string());
+  let span = tracing::info_span!(
+    "request",
+    request_id = %id,
+    method = %request.method(),
+    route = field::Empty,
+    user_uuid = field::Empty,
+  );
+
+  let response = REQUEST_ID.scope(id.clone(), next.run(request)).instrument(span).await;
+  add_to_response(response, &id).await
+}
+
+// Route layer, `propagate` runs before routing so the span gets the route pattern from here.
+// Stays empty on fallback responses.
+pub async fn record_route(matched_path: Option<MatchedPath>, request: Request, next: Next) -> Response {
+  if let Some(path) = &matched_path {
+    Span::current().record("route", path.as_str());
+  }
+  next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use axum::{middleware, routing::get, Router};
+
+  async fn serve() -> String {
+    let app = Router::new()
+      .route("/ping", get(|| async { "pong" }))
+      .route_layer(middleware::from_fn(record_route))
+      .layer(middleware::from_fn(propagate));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{addr}")
+  }
+
+  #[tokio::test]
+  async fn fallback_responses_get_an_id() {
+    let base = serve().await;
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{base}/missing")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert!(response.headers().get(REQUEST_ID_HEADER).is_some());
+
+    let response = client.post(format!("{base}/ping")).send().await.unwrap();
+    assert_eq!(response.status(), 405);
+    assert!(response.headers().get(REQUEST_ID_HEADER).is_some());
+  }
+
+  #[tokio::test]
+  async fn client_ids_are_kept_when_plain() {
+    let base = serve().await;
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{base}/ping")).header(REQUEST_ID_HEADER, "abc-123").send().await.unwrap();
+    assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+
+    let response = client.get(format!("{base}/ping")).header(REQUEST_ID_HEADER, "abc 123").send().await.unwrap();
+    assert_ne!(response.headers()[REQUEST_ID_HEADER], "abc 123");
+  }
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+use axum::{
+  extract::{MatchedPath, Request, State},
+  http::{header, StatusCode},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+use crate::app_state::AppState;
+
+// Seconds, from a fast redis backed route up to a slow provider call
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+pub fn install_recorder() -> PrometheusHandle {
+  PrometheusBuilder::new()
+    .set_buckets(LATENCY_BUCKETS)
+    .expect("Latency buckets must not be empty")
+    .install_recorder()
+    .expect("Unable to install the metrics recorder")
+}
+
+// Drains histogram buffers between scrapes, so memory doesn't grow when nobody scrapes
+pub async fn run_upkeep_task(handle: PrometheusHandle) {
+  let mut interval = tokio::time::interval(Duration::from_secs(5));
+  loop {
+    interval.tick().await;
+    handle.run_upkeep();
+  }
+}
+
+/*** Recorders ***/
+
+// Route layer, the route label is the pattern (/auth/passkeys/{id}) so ids don't blow up the label count
+pub async fn track_requests(matched_path: Option<MatchedPath>, request: Request, next: Next) -> Response {
+  let route = matched_path.map(|path| path.as_str().to_string()).unwrap_or_else(|| "unmatched".to_string());
+  let method = request.method().to_string();
+  let started = Instant::now();
+
+  let response = next.run(request).await;
+
+  let status = response.status().as_u16().to_string();
+  counter!("http_requests_total", "method" => method.clone(), "route" => route.clone(), "status" => status).increment(1);
+  histogram!("http_request_duration_seconds", "method" => method, "route" => route).record(started.elapsed().as_secs_f64());
+  response
+}
+
+// `outcome` is success or the error code sent back to the client
+pub fn record_sign_in(method: &'static str, outcome: &'static str) {
+  counter!("sign_in_total", "method" => method, "outcome" => outcome).increment(1);
+}
+
+// One count per step reached, so drop off shows up as the difference between steps
+pub fn record_sign_up_step(step: &'static str) {
+  counter!("sign_up_funnel_total", "step" => step).increment(1);
+}
+
+// `status` is the Vonage message status ("0" is delivered to the carrier) or a transport failure
+pub fn record_sms_send(status: &str) {
+  counter!("sms_sends_total", "provider" => "vonage", "status" => status.to_string()).increment(1);
+}
+
+pub fn record_argon2(operation: &'static str, started: Instant) {
+  histogram!("argon2_duration_seconds", "operation" => operation).record(started.elapsed().as_secs_f64());
+}
+
+fn record_pool_usage(app_state: &AppState) {
+  let pg_size = app_state.pool.size() as f64;
+  let pg_idle = app_state.pool.num_idle() as f64;
+  gauge!("db_pool_connections", "pool" => "postgres", "state" => "idle").set(pg_idle);
+  gauge!("db_pool_connections", "pool" => "postgres", "state" => "in_use").set(pg_size - pg_idle);
+  gauge!("db_pool_max_connections", "pool" => "postgres").set(app_state.pool.options().get_max_connections() as f64);
+
+  let redis = app_state.redis_pool.status();
+  gauge!("db_pool_connections", "pool" => "redis", "state" => "idle").set(redis.available as f64);
+  gauge!("db_pool_connections", "pool" => "redis", "state" => "in_use").set(redis.size.saturating_sub(redis.available) as f64);
+  gauge!("db_pool_max_connections", "pool" => "redis").set(redis.max_size as f64);
+  gauge!("db_pool_waiting", "pool" => "redis").set(redis.waiting as f64);
+}
+
+/*** Handlers ***/
+
+// Prometheus text format. Pool gauges are read on scrape, everything else is recorded as it happens.
+// Served on metrics_listen_addr rather than the public router, keep that port on the internal network.
+pub async fn handle_metrics(State(app_state): State<AppState>) -> impl IntoResponse {
+  record_pool_usage(&app_state);
+  (StatusCode::OK, [(header::CONTENT_TYPE, "text/plain; version=0.0.4")], app_state.metrics.render())
+}
@@ -0,0 +1,3 @@
+pub mod profile;
+pub mod deletion;
+pub mod export;
@@ -0,0 +1,116 @@
+use std::time::Duration;
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthUser;
+use crate::auth::security_notification::send_security_notification;
+use crate::auth::sessions;
+use crate::auth::db::user_data;
+
+/*** Json Structs **/
+
+#[derive(Serialize)]
+pub struct DeleteAccountResponse {
+  error_code: Option<DeleteAccountError>,
+  purge_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteAccountError {
+  NeedToReauthenticate,
+  InternalError,
+}
+
+const PURGE_INTERVAL_SEC: u64 = 3600;
+
+/*** Helpers ***/
+
+fn warn_response(status_code: StatusCode, error_code: DeleteAccountError) -> (StatusCode, Json<DeleteAccountResponse>) {
+  tracing::warn!(
+    event = "delete_account_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(DeleteAccountResponse {
+    error_code: Some(error_code),
+    purge_at: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<DeleteAccountResponse>) {
+  tracing::error!(
+    event = "delete_account_failure",
+    error_code = ?(DeleteAccountError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(DeleteAccountResponse {
+    error_code: Some(DeleteAccountError::InternalError),
+    purge_at: None,
+  }))
+}
+
+// Hard deletes accounts whose grace period is over, runs for the lifetime of the server
+pub async fn run_purge_task(app_state: AppState) {
+  let mut interval = tokio::time::interval(Duration::from_secs(PURGE_INTERVAL_SEC));
+  loop {
+    interval.tick().await;
+    match user_data::purge_deleted_users(&app_state.pool).await {
+      Ok(0) => {},
+      Ok(count) => tracing::info!(
+        event = "account_purge_success",
+        count = count,
+      ),
+      Err(_) => tracing::error!(
+        event = "account_purge_failure",
+        reason = "db_error",
+      ),
+    }
+  }
+}
+
+/*** Handlers ***/
+
+// Signs the user out everywhere and schedules the purge. Signing in again during the grace period cancels it.
+pub async fn handle_delete_account(State(app_state): State<AppState>, AuthUser { user, claims }: AuthUser) -> (StatusCode, Json<DeleteAccountResponse>) {
+  match sessions::is_recently_authenticated(&app_state, &claims).await {
+    Ok(true) => {},
+    Ok(false) => return warn_response(StatusCode::FORBIDDEN, DeleteAccountError::NeedToReauthenticate),
+    Err(_) => return internal_error_response("db_error"),
+  }
+
+  let grace_sec = app_state.account_deletion_grace_days * 60 * 60 * 24;
+  let Ok(purge_at) = user_data::schedule_deletion(&app_state.pool, &user.uuid, grace_sec).await else {
+    return internal_error_response("db_error");
+  };
+  if sessions::revoke_all_sessions(&app_state, &user.uuid).await.is_err() {
+    return internal_error_response("session_revoke_failed");
+  }
+
+  let message = format!(
+    "Your Getly account is scheduled for deletion and will be permanently erased in {} days. \
+     To keep your account, sign in again before then.",
+    app_state.account_deletion_grace_days,
+  );
+  if send_security_notification(&app_state, &user, "Your Getly account will be deleted", &message).await.is_err() {
+    tracing::error!(
+      event = "delete_account_notification_failure",
+      user_uuid = %user.uuid,
+      reason = "send_email_failed",
+    );
+  }
+
+  tracing::info!(
+    event = "delete_account_scheduled",
+    user_uuid = %user.uuid,
+    purge_at = %purge_at,
+  );
+  (StatusCode::OK, Json(DeleteAccountResponse {
+    error_code: None,
+    purge_at: Some(purge_at),
+  }))
+}
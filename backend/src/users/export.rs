@@ -0,0 +1,142 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use serde::Serialize;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthUser;
+use crate::auth::db::roles::{self, RoleAuditEntry};
+use crate::auth::db::session::{self, SessionData};
+use crate::auth::db::totp;
+use crate::auth::db::webauthn_credential;
+use crate::auth::db::user_data::UserData;
+
+/*** Json Structs **/
+
+// Password hashes are left out, they are a secret and of no use to the user
+#[derive(Serialize)]
+pub struct AccountData {
+  uuid: Uuid,
+  email: String,
+  email_verified: bool,
+  name: String,
+  phone_num: String,
+  created_at: OffsetDateTime,
+  last_seen_at: Option<OffsetDateTime>,
+  picture: Option<String>,
+  roles: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PasskeyData {
+  credential_id: String,
+  name: String,
+  created_at: OffsetDateTime,
+  last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct LinkedIdentities {
+  password: bool,
+  google_sub: Option<String>,
+  phone_num: String,
+  totp_enabled_at: Option<OffsetDateTime>,
+  passkeys: Vec<PasskeyData>,
+}
+
+#[derive(Serialize)]
+pub struct AccountExport {
+  exported_at: OffsetDateTime,
+  account: AccountData,
+  linked_identities: LinkedIdentities,
+  sign_in_history: Vec<SessionData>,
+  role_changes: Vec<RoleAuditEntry>,
+}
+
+#[derive(Serialize)]
+pub struct ExportResponse {
+  error_code: Option<ExportError>,
+  export: Option<AccountExport>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportError {
+  InternalError,
+}
+
+/*** Helpers ***/
+
+async fn build_export(app_state: &AppState, user: UserData) -> Result<AccountExport, sqlx::Error> {
+  let sign_in_history = session::get_all_sessions(&app_state.pool, &user.uuid).await?;
+  let role_changes = roles::get_audit_log_for_user(&app_state.pool, &user.uuid).await?;
+  let totp_enabled_at = totp::get_factor(&app_state.pool, &user.uuid).await?.and_then(|factor| factor.enabled_at);
+  // Public keys and counters are left out with the password hash
+  let passkeys = webauthn_credential::get_credentials(&app_state.pool, &user.uuid).await?
+    .into_iter()
+    .map(|credential| PasskeyData {
+      credential_id: credential.credential_id,
+      name: credential.name,
+      created_at: credential.created_at,
+      last_used_at: credential.last_used_at,
+    })
+    .collect();
+
+  Ok(AccountExport {
+    exported_at: OffsetDateTime::now_utc(),
+    linked_identities: LinkedIdentities {
+      password: user.password_hash.is_some(),
+      google_sub: user.google_sub,
+      phone_num: user.phone_num.clone(),
+      totp_enabled_at,
+      passkeys,
+    },
+    account: AccountData {
+      uuid: user.uuid,
+      email: user.email,
+      email_verified: user.email_verified,
+      name: user.name,
+      phone_num: user.phone_num,
+      created_at: user.created_at,
+      last_seen_at: user.last_seen_at,
+      picture: user.picture,
+      roles: user.roles,
+    },
+    sign_in_history,
+    role_changes,
+  })
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<ExportResponse>) {
+  tracing::error!(
+    event = "export_failure",
+    error_code = ?(ExportError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(ExportResponse {
+    error_code: Some(ExportError::InternalError),
+    export: None,
+  }))
+}
+
+/*** Handlers ***/
+
+// Everything we hold on the user, for data portability requests
+pub async fn handle_export(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser) -> (StatusCode, Json<ExportResponse>) {
+  let user_uuid = user.uuid;
+  let Ok(export) = build_export(&app_state, user).await else {
+    return internal_error_response("db_error");
+  };
+
+  tracing::info!(
+    event = "export_success",
+    user_uuid = %user_uuid,
+  );
+  (StatusCode::OK, Json(ExportResponse {
+    error_code: None,
+    export: Some(export),
+  }))
+}
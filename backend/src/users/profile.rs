@@ -0,0 +1,193 @@
+use axum::{
+  extract::{Json, State},
+  http::StatusCode,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+use crate::auth::auth_user::AuthUser;
+use crate::auth::db::user_data::{self, UserData};
+
+/*** Json Structs **/
+
+// The editable fields, anything else in the body (email, phone_num, google_sub...) is rejected
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateProfileRequest {
+  name: Option<String>,
+  picture: Option<String>, // Empty string removes the picture
+}
+
+#[derive(Serialize)]
+pub struct ProfileResponse {
+  error_code: Option<ProfileError>,
+  user_data: Option<UserData>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileError {
+  InvalidName,
+  InvalidPicture,
+  UserNotFound,
+  InternalError,
+}
+
+const NAME_MIN_LENGTH: usize = 2;
+const NAME_MAX_LENGTH: usize = 50;
+const PICTURE_MAX_LENGTH: usize = 2048;
+
+/*** Helpers ***/
+
+fn is_name_letter(c: char) -> bool {
+  c.is_ascii_alphabetic()
+    || (matches!(c, '\u{00C0}'..='\u{024F}') && !matches!(c, '\u{00D7}' | '\u{00F7}')) // Latin with accents, without × and ÷
+    || matches!(c, '\u{05D0}'..='\u{05EA}') // Hebrew
+    || matches!(c, '\u{0620}'..='\u{064A}') // Arabic
+}
+
+// Hebrew, Arabic and Latin letters, with spaces, hyphens and apostrophes between them
+fn normalize_name(name: &str) -> Option<String> {
+  let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+  let length = name.chars().count();
+  if !(NAME_MIN_LENGTH..=NAME_MAX_LENGTH).contains(&length) {
+    return None;
+  }
+  let valid_chars = name.chars().all(|c| is_name_letter(c) || matches!(c, ' ' | '-' | '\'' | '\u{05F3}'));
+  let starts_with_letter = name.chars().next().is_some_and(is_name_letter);
+  (valid_chars && starts_with_letter).then_some(name)
+}
+
+// Only https links to hosts we trust to serve images
+fn validate_picture(picture: &str, allowed_hosts: &[String]) -> bool {
+  if picture.len() > PICTURE_MAX_LENGTH {
+    return false;
+  }
+  match Url::parse(picture) {
+    Ok(url) => url.scheme() == "https" && url.host_str().is_some_and(|host| allowed_hosts.iter().any(|allowed| allowed == host)),
+    Err(_) => false,
+  }
+}
+
+fn warn_response(status_code: StatusCode, error_code: ProfileError) -> (StatusCode, Json<ProfileResponse>) {
+  tracing::warn!(
+    event = "update_profile_failure",
+    error_code = ?(error_code),
+  );
+  (status_code, Json(ProfileResponse {
+    error_code: Some(error_code),
+    user_data: None,
+  }))
+}
+
+fn internal_error_response(reason: &str) -> (StatusCode, Json<ProfileResponse>) {
+  tracing::error!(
+    event = "update_profile_failure",
+    error_code = ?(ProfileError::InternalError),
+    reason = reason,
+  );
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(ProfileResponse {
+    error_code: Some(ProfileError::InternalError),
+    user_data: None,
+  }))
+}
+
+/*** Handlers ***/
+
+pub async fn handle_update_profile(State(app_state): State<AppState>, AuthUser { user, .. }: AuthUser, Json(payload): Json<UpdateProfileRequest>) -> (StatusCode, Json<ProfileResponse>) {
+  let name = match payload.name {
+    Some(name) => match normalize_name(&name) {
+      Some(name) => name,
+      None => return warn_response(StatusCode::BAD_REQUEST, ProfileError::InvalidName),
+    },
+    None => user.name,
+  };
+
+  let picture = match payload.picture.as_deref() {
+    Some("") => None,
+    Some(picture) if validate_picture(picture, &app_state.picture_allowed_hosts) => Some(picture.to_string()),
+    Some(_) => return warn_response(StatusCode::BAD_REQUEST, ProfileError::InvalidPicture),
+    None => user.picture,
+  };
+
+  match user_data::update_profile(&app_state.pool, &user.uuid, &name, picture.as_deref()).await {
+    Ok(Some(user)) => {
+      tracing::info!(
+        event = "update_profile_success",
+        user_uuid = %user.uuid,
+      );
+      (StatusCode::OK, Json(ProfileResponse {
+        error_code: None,
+        user_data: Some(user),
+      }))
+    },
+    Ok(None) => warn_response(StatusCode::NOT_FOUND, ProfileError::UserNotFound),
+    Err(_) => internal_error_response("db_error"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn allowed_hosts() -> Vec<String> {
+    vec!["lh3.googleusercontent.com".to_string()]
+  }
+
+  #[test]
+  fn normalize_name_collapses_whitespace() {
+    assert_eq!(normalize_name("  Dana   Cohen "), Some("Dana Cohen".to_string()));
+  }
+
+  #[test]
+  fn normalize_name_accepts_hebrew_arabic_and_accented_latin() {
+    assert_eq!(normalize_name("דנה כהן"), Some("דנה כהן".to_string()));
+    assert_eq!(normalize_name("محمد"), Some("محمد".to_string()));
+    assert_eq!(normalize_name("Zoë O'Brien-Núñez"), Some("Zoë O'Brien-Núñez".to_string()));
+    assert_eq!(normalize_name("צ׳רלי"), Some("צ׳רלי".to_string()));
+  }
+
+  #[test]
+  fn normalize_name_checks_length() {
+    assert_eq!(normalize_name("A"), None);
+    assert_eq!(normalize_name(&"a".repeat(NAME_MAX_LENGTH)), Some("a".repeat(NAME_MAX_LENGTH)));
+    assert_eq!(normalize_name(&"a".repeat(NAME_MAX_LENGTH + 1)), None);
+    assert_eq!(normalize_name("   "), None);
+  }
+
+  #[test]
+  fn normalize_name_rejects_symbols() {
+    assert_eq!(normalize_name("Dana×Cohen"), None);
+    assert_eq!(normalize_name("Dana÷Cohen"), None);
+    assert_eq!(normalize_name("Dana <script>"), None);
+    assert_eq!(normalize_name("Dana2"), None);
+    assert_eq!(normalize_name("😀 Dana"), None);
+  }
+
+  #[test]
+  fn normalize_name_must_start_with_a_letter() {
+    assert_eq!(normalize_name("-Dana"), None);
+    assert_eq!(normalize_name("'Dana"), None);
+  }
+
+  #[test]
+  fn validate_picture_accepts_allowed_https_hosts() {
+    assert!(validate_picture("https://lh3.googleusercontent.com/a/photo.jpg", &allowed_hosts()));
+  }
+
+  #[test]
+  fn validate_picture_rejects_other_schemes_and_hosts() {
+    assert!(!validate_picture("http://lh3.googleusercontent.com/a/photo.jpg", &allowed_hosts()));
+    assert!(!validate_picture("https://evil.example/photo.jpg", &allowed_hosts()));
+    assert!(!validate_picture("https://lh3.googleusercontent.com.evil.example/photo.jpg", &allowed_hosts()));
+    assert!(!validate_picture("javascript:alert(1)", &allowed_hosts()));
+    assert!(!validate_picture("not a url", &allowed_hosts()));
+  }
+
+  #[test]
+  fn validate_picture_checks_length() {
+    let long = format!("https://lh3.googleusercontent.com/{}", "a".repeat(PICTURE_MAX_LENGTH));
+    assert!(!validate_picture(&long, &allowed_hosts()));
+  }
+}
@@ -0,0 +1,55 @@
+const BACKEND_URL = 'http://localhost:3000';
+
+// Shared by confirm-email-change.html and cancel-email-change.html, data-action picks the endpoint
+const action = document.currentScript.dataset.action; // confirm or cancel
+
+const statusMsg = document.getElementById('status-msg');
+const errorMsg = document.getElementById('error-msg');
+
+const ERROR_MESSAGES = {
+  invalid_token: 'הקישור פג תוקף או אינו תקין.',
+  link_already_used: 'הקישור כבר נוצל.',
+  no_pending_change: 'אין שינוי אימייל ממתין. ייתכן שהוא בוטל או הוחלף בבקשה חדשה.',
+  email_taken: 'כתובת האימייל כבר בשימוש בחשבון אחר.',
+};
+
+function showError(text) {
+  statusMsg.classList.add('hidden');
+  errorMsg.textContent = text;
+}
+
+// The emails link here with ?token=..., the backend only accepts it as a POST
+async function submitLink() {
+  const token = new URLSearchParams(window.location.search).get('token');
+  if (!token) {
+    showError('הקישור אינו תקין.');
+    return;
+  }
+
+  try {
+    const response = await fetch(`${BACKEND_URL}/auth/email/change/${action}`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ token }),
+    });
+    const data = await response.json();
+
+    if (!response.ok || data.error_code) {
+      showError(ERROR_MESSAGES[data.error_code] || 'שגיאת שרת. נסו שוב מאוחר יותר.');
+      return;
+    }
+
+    if (action === 'confirm') {
+      statusMsg.textContent = 'כתובת האימייל עודכנה בהצלחה!';
+    } else {
+      // A change that was already confirmed is rolled back and every session signed out
+      localStorage.removeItem('jwt_token');
+      localStorage.removeItem('refresh_token');
+      statusMsg.textContent = 'השינוי בוטל והאימייל הקודם נשמר. מומלץ להתחבר ולאפס את הסיסמה.';
+    }
+  } catch (err) {
+    showError('שגיאת רשת. נסו שוב מאוחר יותר.');
+  }
+}
+
+submitLink();
@@ -46,6 +46,7 @@ async function handleCredentialResponse(response) {
     }
 
     localStorage.setItem('jwt_token', data.jwt_token);
+    localStorage.setItem('refresh_token', data.refresh_token);
     window.location.href = REDIRECT_ON_SUCCESS_URL;
 
   } catch (err) {
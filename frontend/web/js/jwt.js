@@ -1,4 +1,5 @@
 const VERIFY_ENDPOINT = '/auth/me';
+const REFRESH_ENDPOINT = '/auth/refresh';
 const BACKEND_URL = "http://localhost:3000"
 
 function getToken() {
@@ -7,6 +8,33 @@ function getToken() {
 
 function removeToken() {
   localStorage.removeItem('jwt_token');
+  localStorage.removeItem('refresh_token');
+}
+
+// Access tokens are short-lived, swap the refresh token for a new pair
+async function refreshToken() {
+  const refresh = localStorage.getItem('refresh_token');
+  if (!refresh) throw new Error('No refresh token');
+
+  const res = await fetch(`${BACKEND_URL}${REFRESH_ENDPOINT}`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ refresh_token: refresh }),
+  });
+  if (!res.ok) throw new Error('Refresh failed');
+
+  const data = await res.json();
+  localStorage.setItem('jwt_token', data.jwt_token);
+  localStorage.setItem('refresh_token', data.refresh_token);
+  return data.jwt_token;
+}
+
+async function verifyOrRefresh(token) {
+  try {
+    return await verifyJwt(token);
+  } catch {
+    return verifyJwt(await refreshToken());
+  }
 }
 
 async function verifyJwt(token) {
@@ -29,7 +57,7 @@ async function verifyJwt(token) {
     if (!token) return (window.location.href = redirect); // there is no token so go to login
 
     try {
-      const data = await verifyJwt(token); // verify token
+      const data = await verifyOrRefresh(token); // verify token
       window.currentUser = data.user_data;
     } catch {
       removeToken();
@@ -41,7 +69,7 @@ async function verifyJwt(token) {
     if (!token) return; // there is no token so stay in login
 
     try {
-      const data = await verifyJwt(token);
+      const data = await verifyOrRefresh(token);
       if (data?.user_data) {
         window.location.href = redirect;
       }
@@ -0,0 +1,140 @@
+const BACKEND_URL = 'http://localhost:3000';
+const REDIRECT_AFTER_LOGIN = 'dashboard.html';
+// Set when the link was bound to this browser, sent back with the token when the link is opened
+const CONFIRMATION_CODE_KEY = 'magic_link_confirmation_code';
+
+const requestForm = document.getElementById('request-form');
+const requestMsg = document.getElementById('request-msg');
+const sentMsg = document.getElementById('sent-msg');
+const signInForm = document.getElementById('sign-in-form');
+const signInMsg = document.getElementById('sign-in-msg');
+const codeInput = document.getElementById('confirmation-code');
+const totpForm = document.getElementById('totp-form');
+const totpMsg = document.getElementById('totp-msg');
+
+const token = new URLSearchParams(window.location.search).get('token');
+let challengeToken = null;
+
+function saveTokens(data) {
+  localStorage.setItem('jwt_token', data.jwt_token);
+  localStorage.setItem('refresh_token', data.refresh_token);
+  localStorage.removeItem(CONFIRMATION_CODE_KEY);
+  window.location.href = REDIRECT_AFTER_LOGIN;
+}
+
+async function postJson(path, body) {
+  const response = await fetch(`${BACKEND_URL}${path}`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify(body),
+  });
+  return { ok: response.ok, data: await response.json() };
+}
+
+// --- Step 1: request a link ---
+requestForm.addEventListener('submit', async (e) => {
+  e.preventDefault();
+  requestMsg.textContent = '';
+  const email = document.getElementById('email').value.trim();
+  if (!email) {
+    requestMsg.textContent = 'אנא הזינו אימייל.';
+    return;
+  }
+  const bindToDevice = document.getElementById('bind-to-device').checked;
+
+  try {
+    // Always answers OK, whether or not the email has an account
+    const { data } = await postJson('/auth/sign-in/magic-link/send', { email, bind_to_device: bindToDevice });
+    requestForm.classList.add('hidden');
+    sentMsg.classList.remove('hidden');
+    if (data.confirmation_code) {
+      localStorage.setItem(CONFIRMATION_CODE_KEY, data.confirmation_code);
+      sentMsg.textContent = `אם קיים חשבון, נשלח אליו קישור. פתחו אותו בדפדפן הזה, או הזינו את הקוד ${data.confirmation_code} אם תפתחו אותו במכשיר אחר.`;
+    } else {
+      localStorage.removeItem(CONFIRMATION_CODE_KEY);
+      sentMsg.textContent = 'אם קיים חשבון, נשלח אליו קישור להתחברות.';
+    }
+  } catch (err) {
+    requestMsg.textContent = 'שגיאת רשת. נסו שוב מאוחר יותר.';
+  }
+});
+
+// --- Step 2: exchange the link for tokens ---
+async function signIn(confirmationCode) {
+  signInMsg.textContent = '';
+  try {
+    const { ok, data } = await postJson('/auth/sign-in', {
+      method: 'magic_link',
+      token,
+      confirmation_code: confirmationCode || null,
+    });
+
+    if (data.error_code === 'second_factor_required') {
+      challengeToken = data.challenge_token;
+      signInForm.classList.add('hidden');
+      totpForm.classList.remove('hidden');
+      return;
+    }
+    if (!ok || data.error_code) {
+      signInForm.classList.remove('hidden');
+      signInForm.querySelector('button').disabled = true;
+      // The link is used up by any attempt, a wrong code included
+      signInMsg.textContent = data.error_code === 'invalid_credentials'
+        ? 'הקישור פג תוקף, כבר נוצל, או שהקוד שגוי. בקשו קישור חדש.'
+        : 'שגיאת שרת. נסו שוב מאוחר יותר.';
+      return;
+    }
+    saveTokens(data);
+  } catch (err) {
+    signInMsg.textContent = 'שגיאת רשת. נסו שוב מאוחר יותר.';
+  }
+}
+
+signInForm.addEventListener('submit', async (e) => {
+  e.preventDefault();
+  const code = codeInput.value.trim();
+  if (code && !/^\d{6}$/.test(code)) {
+    signInMsg.textContent = 'הקוד חייב להכיל 6 ספרות.';
+    return;
+  }
+  await signIn(code);
+});
+
+// --- Step 3: second factor ---
+totpForm.addEventListener('submit', async (e) => {
+  e.preventDefault();
+  totpMsg.textContent = '';
+  const code = document.getElementById('totp-code').value.trim();
+  try {
+    const { ok, data } = await postJson('/auth/sign-in/totp', { challenge_token: challengeToken, code });
+    if (!ok || data.error_code) {
+      switch (data.error_code) {
+        case 'invalid_second_factor':
+          totpMsg.textContent = 'קוד שגוי. נסו שוב.';
+          break;
+        case 'second_factor_locked':
+          totpMsg.textContent = 'ניסיונות רבים מדי. נסו שוב מאוחר יותר.';
+          break;
+        default:
+          totpMsg.textContent = 'פג תוקף ההתחברות. בקשו קישור חדש.';
+          break;
+      }
+      return;
+    }
+    saveTokens(data);
+  } catch (err) {
+    totpMsg.textContent = 'שגיאת רשת. נסו שוב מאוחר יותר.';
+  }
+});
+
+// A link opened on the browser that asked for it signs in right away, elsewhere the code is asked for
+if (!token) {
+  requestForm.classList.remove('hidden');
+} else {
+  const storedCode = localStorage.getItem(CONFIRMATION_CODE_KEY);
+  if (storedCode) {
+    signIn(storedCode);
+  } else {
+    signInForm.classList.remove('hidden');
+  }
+}
@@ -219,6 +219,7 @@ codeForm.addEventListener('submit', async (e) => {
 
       // Store tokens and user data
       localStorage.setItem('jwt_token', completeData.jwt_token);
+      localStorage.setItem('refresh_token', completeData.refresh_token);
       localStorage.setItem('user_data', JSON.stringify(completeData.user_data));
 
       // Redirect to the dashboard on final success
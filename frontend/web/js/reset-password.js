@@ -0,0 +1,68 @@
+const BACKEND_URL = 'http://localhost:3000';
+
+const resetForm = document.getElementById('reset-form');
+const errorMsg = document.getElementById('error-msg');
+const successMsg = document.getElementById('success-msg');
+
+// Set with error_code weak_password, matches PasswordPolicyError on the backend
+const POLICY_MESSAGES = {
+  too_short: 'הסיסמה קצרה מדי.',
+  too_long: 'הסיסמה ארוכה מדי.',
+  contains_email: 'הסיסמה לא יכולה להכיל את האימייל שלך.',
+  breached: 'הסיסמה הופיעה בדליפת מידע, אנא בחרו סיסמה אחרת.',
+  too_weak: 'הסיסמה חלשה מדי, נסו סיסמה ארוכה ומגוונת יותר.',
+};
+
+const token = new URLSearchParams(window.location.search).get('token');
+if (!token) {
+  errorMsg.textContent = 'הקישור אינו תקין. בקשו קישור איפוס חדש.';
+  resetForm.querySelector('button').disabled = true;
+}
+
+resetForm.addEventListener('submit', async (e) => {
+  e.preventDefault();
+  errorMsg.textContent = '';
+
+  const password = document.getElementById('password').value;
+  const passwordConfirm = document.getElementById('password-confirm').value;
+  if (!password) {
+    errorMsg.textContent = 'אנא הזינו סיסמה חדשה.';
+    return;
+  }
+  if (password !== passwordConfirm) {
+    errorMsg.textContent = 'הסיסמאות אינן תואמות.';
+    return;
+  }
+
+  try {
+    const response = await fetch(`${BACKEND_URL}/auth/password/reset`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ token, password }),
+    });
+    const data = await response.json();
+
+    if (!response.ok || data.error_code) {
+      switch (data.error_code) {
+        case 'weak_password':
+          errorMsg.textContent = POLICY_MESSAGES[data.policy_error] || 'הסיסמה אינה עומדת בדרישות.';
+          break;
+        case 'invalid_token':
+          errorMsg.textContent = 'הקישור פג תוקף או כבר נוצל. בקשו קישור איפוס חדש.';
+          break;
+        default:
+          errorMsg.textContent = 'שגיאת שרת. נסו שוב מאוחר יותר.';
+          break;
+      }
+      return;
+    }
+
+    // Every session was signed out, the old tokens on this device are no good either
+    localStorage.removeItem('jwt_token');
+    localStorage.removeItem('refresh_token');
+    resetForm.classList.add('hidden');
+    successMsg.classList.remove('hidden');
+  } catch (err) {
+    errorMsg.textContent = 'שגיאת רשת. נסו שוב מאוחר יותר.';
+  }
+});
@@ -13,8 +13,9 @@ const errorMessage = document.getElementById('error-message');
 // ==========================
 // Token Helpers
 // ==========================
-function saveToken(token) {
+function saveToken(token, refreshToken) {
   localStorage.setItem('jwt_token', token);
+  localStorage.setItem('refresh_token', refreshToken);
 }
 
 function getToken() {
@@ -23,6 +24,7 @@ function getToken() {
 
 function removeToken() {
   localStorage.removeItem('jwt_token');
+  localStorage.removeItem('refresh_token');
 }
 
 // ==========================
@@ -70,7 +72,7 @@ async function handleCredentialResponse(response) {
     }
 
     const data = await backendResponse.json();
-    saveToken(data.jwt_token);
+    saveToken(data.jwt_token, data.refresh_token);
     window.location.href = REDIRECT_AFTER_LOGIN;
 
   } catch (err) {
@@ -105,7 +107,7 @@ async function handleLoginSubmit(event) {
 
   try {
     const data = await login(email, password);
-    saveToken(data.jwt_token);
+    saveToken(data.jwt_token, data.refresh_token);
     window.location.href = REDIRECT_AFTER_LOGIN;
   } catch (err) {
     errorMessage.textContent = 'Email or password are incorrect.';
@@ -0,0 +1,80 @@
+const BACKEND_URL = 'http://localhost:3000';
+
+const statusMsg = document.getElementById('status-msg');
+const errorMsg = document.getElementById('error-msg');
+const resendForm = document.getElementById('resend-form');
+const resendMsg = document.getElementById('resend-msg');
+
+function showError(text, offerResend) {
+  statusMsg.classList.add('hidden');
+  errorMsg.textContent = text;
+  if (offerResend) resendForm.classList.remove('hidden');
+}
+
+// The email links here with ?token=..., the backend only accepts it as a POST
+async function verifyEmail() {
+  const token = new URLSearchParams(window.location.search).get('token');
+  if (!token) {
+    showError('הקישור אינו תקין.', true);
+    return;
+  }
+
+  try {
+    const response = await fetch(`${BACKEND_URL}/auth/verify-email`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ token }),
+    });
+    const data = await response.json();
+
+    if (!response.ok || data.error_code) {
+      switch (data.error_code) {
+        case 'link_already_used':
+          showError('הקישור כבר נוצל. אם האימייל עדיין לא אומת, בקשו קישור חדש.', true);
+          break;
+        case 'invalid_token':
+          showError('הקישור פג תוקף או אינו תקין.', true);
+          break;
+        default:
+          showError('שגיאת שרת. נסו שוב מאוחר יותר.', false);
+          break;
+      }
+      return;
+    }
+
+    statusMsg.textContent = 'האימייל אומת בהצלחה!';
+  } catch (err) {
+    showError('שגיאת רשת. נסו שוב מאוחר יותר.', false);
+  }
+}
+
+resendForm.addEventListener('submit', async (e) => {
+  e.preventDefault();
+  const email = document.getElementById('email').value.trim();
+  if (!email) {
+    resendMsg.textContent = 'אנא הזינו אימייל.';
+    return;
+  }
+
+  try {
+    const response = await fetch(`${BACKEND_URL}/auth/verify-email/resend`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ email }),
+    });
+    const data = await response.json();
+
+    if (data.error_code === 'need_to_wait_before_resend') {
+      resendMsg.textContent = 'יש להמתין לפני שליחת קישור נוסף.';
+    } else if (!response.ok || data.error_code) {
+      resendMsg.textContent = 'שגיאת שרת. נסו שוב מאוחר יותר.';
+    } else {
+      // Same answer for unknown emails, the backend doesn't say whether the account exists
+      resendMsg.textContent = 'אם החשבון קיים ולא אומת, נשלח אליו קישור חדש.';
+    }
+  } catch (err) {
+    resendMsg.textContent = 'שגיאת רשת. נסו שוב מאוחר יותר.';
+  }
+});
+
+verifyEmail();